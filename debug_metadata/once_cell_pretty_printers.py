@@ -0,0 +1,257 @@
+"""Pretty-printers for once_cell types.
+
+GDB loads this automatically from the `.debug_gdb_scripts` section of
+binaries built with once_cell. In LLDB, run
+`command script import debug_metadata/once_cell_pretty_printers.py`.
+
+Empty cells and unforced lazies show as `Uninit`, and filled ones as their
+value. `sync` types also show `Initializing` and `Poisoned`.
+
+The field paths below follow the private layout in src/lib.rs. To check them
+against a build, run this file on a binary with DWARF debuginfo:
+
+    cargo test --no-run
+    python3 debug_metadata/once_cell_pretty_printers.py target/debug/deps/once_cell-<hash>
+
+or `cargo test -- --ignored debugger_field_paths_exist`, which does both.
+
+It needs `llvm-dwarfdump` on `PATH`. once_cell.natvis reads the same fields,
+plus the MSVC layout of `Option` (`variant0` for `None`, `variant1.value.__0`
+for the `Some` payload), which only a Windows build can check: in the Visual
+Studio watch window, `unsync::OnceCell`, `sync::OnceCell` and `sync::Lazy`
+should read `Uninit` before and their value after initialization.
+"""
+
+import re
+
+# The states of the `sync` backends, other than `COMPLETE` (2).
+SYNC_STATES = {0: "Uninit", 1: "Initializing", 3: "Poisoned"}
+SYNC_COMPLETE = 2
+
+ONCE_CELL = re.compile(r"^once_cell::(un)?sync::OnceCell<.*>$")
+SYNC_ONCE_CELL = re.compile(r"^once_cell::sync::OnceCell<.*>$")
+UNSYNC_LAZY = re.compile(r"^once_cell::unsync::Lazy<.*>$")
+SYNC_LAZY = re.compile(r"^once_cell::sync::Lazy<.*>$")
+ONCE_BOX = re.compile(r"^once_cell::race::OnceBox<.*>$")
+
+CELL_VALUE = "inner.value"
+BACKEND_STATE = "backend.state.v.value"
+LAZY_STATE = "state.value.value"
+LAZY_VALUE = "data.value.value.value"
+BOX_PTR = "inner.p.value"
+
+# The paths each type is read through. `sync::OnceCell` only reads the
+# backend state when its backend has one, so that path is optional.
+FIELD_PATHS = [
+    (ONCE_CELL, [CELL_VALUE], []),
+    (SYNC_ONCE_CELL, [], [BACKEND_STATE]),
+    (UNSYNC_LAZY, [LAZY_STATE, LAZY_VALUE], []),
+    (SYNC_LAZY, [BACKEND_STATE, LAZY_VALUE], []),
+    (ONCE_BOX, [BOX_PTR], []),
+]
+
+
+def _option_text(text):
+    return "Uninit" if text is None or text.endswith("None") else text
+
+
+try:
+    import gdb
+    import gdb.types
+except ImportError:
+    gdb = None
+
+if gdb is not None:
+
+    def _field(val, path):
+        for name in path.split("."):
+            val = val[name]
+        return val
+
+    def _state(val):
+        try:
+            return int(_field(val, BACKEND_STATE))
+        except gdb.error:
+            return None
+
+    class OnceCellPrinter:
+        def __init__(self, val):
+            self.val = val
+
+        def to_string(self):
+            state = _state(self.val)
+            if state is not None and state != SYNC_COMPLETE:
+                return SYNC_STATES.get(state, "Uninit")
+            return _option_text(str(_field(self.val, CELL_VALUE)))
+
+    class UnsyncLazyPrinter:
+        def __init__(self, val):
+            self.val = val
+
+        def to_string(self):
+            state = str(_field(self.val, LAZY_STATE)).split("::")[-1]
+            if state == "Forced":
+                return _field(self.val, LAZY_VALUE)
+            return "Poisoned" if state == "Poisoned" else "Uninit"
+
+    class SyncLazyPrinter:
+        def __init__(self, val):
+            self.val = val
+
+        def to_string(self):
+            state = _state(self.val)
+            if state == SYNC_COMPLETE:
+                return _field(self.val, LAZY_VALUE)
+            return SYNC_STATES.get(state, "Uninit")
+
+    class OnceBoxPrinter:
+        def __init__(self, val):
+            self.val = val
+
+        def to_string(self):
+            ptr = _field(self.val, BOX_PTR)
+            return "Uninit" if int(ptr) == 0 else ptr.dereference()
+
+    PRINTERS = [
+        (ONCE_CELL, OnceCellPrinter),
+        (UNSYNC_LAZY, UnsyncLazyPrinter),
+        (SYNC_LAZY, SyncLazyPrinter),
+        (ONCE_BOX, OnceBoxPrinter),
+    ]
+
+    def lookup(val):
+        name = gdb.types.get_basic_type(val.type).tag
+        for pattern, printer in PRINTERS:
+            if name is not None and pattern.match(name):
+                return printer(val)
+        return None
+
+    (gdb.current_objfile() or gdb).pretty_printers.append(lookup)
+
+
+def _lldb_field(valobj, path):
+    return valobj.GetValueForExpressionPath("." + path)
+
+
+def _lldb_text(value):
+    return value.GetSummary() or value.GetValue()
+
+
+def _lldb_state(valobj):
+    state = _lldb_field(valobj, BACKEND_STATE)
+    return state.GetValueAsUnsigned() if state.IsValid() else None
+
+
+def once_cell_summary(valobj, _dict):
+    state = _lldb_state(valobj)
+    if state is not None and state != SYNC_COMPLETE:
+        return SYNC_STATES.get(state, "Uninit")
+    return _option_text(_lldb_field(valobj, CELL_VALUE).GetSummary())
+
+
+def unsync_lazy_summary(valobj, _dict):
+    state = (_lldb_field(valobj, LAZY_STATE).GetValue() or "").split("::")[-1]
+    if state == "Forced":
+        return _lldb_text(_lldb_field(valobj, LAZY_VALUE))
+    return "Poisoned" if state == "Poisoned" else "Uninit"
+
+
+def sync_lazy_summary(valobj, _dict):
+    state = _lldb_state(valobj)
+    if state == SYNC_COMPLETE:
+        return _lldb_text(_lldb_field(valobj, LAZY_VALUE))
+    return SYNC_STATES.get(state, "Uninit")
+
+
+def once_box_summary(valobj, _dict):
+    ptr = _lldb_field(valobj, BOX_PTR)
+    if ptr.GetValueAsUnsigned() == 0:
+        return "Uninit"
+    return _lldb_text(ptr.Dereference())
+
+
+def __lldb_init_module(debugger, _dict):
+    for function, pattern in [
+        ("once_cell_summary", ONCE_CELL),
+        ("unsync_lazy_summary", UNSYNC_LAZY),
+        ("sync_lazy_summary", SYNC_LAZY),
+        ("once_box_summary", ONCE_BOX),
+    ]:
+        debugger.HandleCommand(
+            'type summary add -F %s.%s -x "%s"' % (__name__, function, pattern.pattern)
+        )
+
+
+def _dwarf_types(binary):
+    """Maps each struct and union in `binary` to its members' types."""
+    import subprocess
+
+    dump = subprocess.run(
+        ["llvm-dwarfdump", "--debug-info", binary],
+        check=True,
+        stdout=subprocess.PIPE,
+        universal_newlines=True,
+    ).stdout
+    entry = re.compile(r"^0x[0-9a-f]+:( +)(DW_TAG_\w+|NULL)")
+    attr = re.compile(r'^\s+DW_AT_(name|type)\s+\((?:0x[0-9a-f]+ )?"(.*)"\)$')
+    named = ("DW_TAG_namespace", "DW_TAG_structure_type", "DW_TAG_union_type")
+    types, scopes, tag = {}, [], None
+    for line in dump.splitlines():
+        m = entry.match(line)
+        if m:
+            depth, tag = len(m.group(1)), m.group(2)
+            while scopes and scopes[-1][0] >= depth:
+                scopes.pop()
+            if tag != "NULL":
+                scopes.append([depth, tag, None, None])
+            continue
+        m = attr.match(line)
+        if not m or not scopes:
+            continue
+        scope = scopes[-1]
+        if m.group(1) == "name":
+            scope[2] = m.group(2)
+            if tag in ("DW_TAG_structure_type", "DW_TAG_union_type"):
+                path = [s[2] for s in scopes if s[1] in named]
+                types.setdefault("::".join(path), {})
+        elif tag == "DW_TAG_member" and len(scopes) > 1:
+            parent = scopes[-2]
+            path = [s[2] for s in scopes[:-1] if s[1] in named]
+            if parent[1] in named[1:]:
+                types.setdefault("::".join(path), {})[scope[2]] = m.group(2)
+    return types
+
+
+def _resolves(types, name, path):
+    for field in path.split("."):
+        member = types.get(name, {}).get(field)
+        if member is None:
+            return False
+        name = member
+    return True
+
+
+def check(binary):
+    """Checks that every path the printers read exists in `binary`."""
+    types = _dwarf_types(binary)
+    failures = []
+    for pattern, required, optional in FIELD_PATHS:
+        names = [name for name in types if pattern.match(name)]
+        if not names:
+            failures.append("no type matches %s" % pattern.pattern)
+        for path in required:
+            failures += ["%s has no %s" % (n, path) for n in names if not _resolves(types, n, path)]
+        for path in optional:
+            if names and not any(_resolves(types, n, path) for n in names):
+                failures.append("no %s has %s" % (pattern.pattern, path))
+    return failures
+
+
+# GDB runs embedded scripts as `__main__` too.
+if __name__ == "__main__" and gdb is None:
+    import sys
+
+    failures = check(sys.argv[1])
+    for failure in failures:
+        print(failure)
+    sys.exit(1 if failures else 0)
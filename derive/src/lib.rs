@@ -0,0 +1,193 @@
+//! `#[derive(Singleton)]` and `#[once]`, re-exported by `once_cell` behind
+//! its `derive` and `macros` features.
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/// Generates `fn instance() -> &'static Self`, backed by a
+/// `once_cell::sync::Lazy` built with `Default::default`, or with the
+/// function named by `#[singleton(constructor = path::to::fn)]`.
+#[proc_macro_derive(Singleton, attributes(singleton))]
+pub fn derive_singleton(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(tokens) => tokens,
+        Err(msg) => format!("::core::compile_error!({:?});", msg)
+            .parse()
+            .unwrap(),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, String> {
+    let mut constructor = None;
+    let mut tokens = input.into_iter().peekable();
+    let name = loop {
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '#' => {
+                if let Some(TokenTree::Group(attr)) = tokens.next() {
+                    if let Some(path) = singleton_attr(attr.stream())? {
+                        constructor = Some(path);
+                    }
+                }
+            }
+            Some(TokenTree::Ident(kw))
+                if ["struct", "enum", "union"].contains(&&*kw.to_string()) =>
+            {
+                match tokens.next() {
+                    Some(TokenTree::Ident(name)) => break name.to_string(),
+                    _ => return Err("expected a type name".into()),
+                }
+            }
+            Some(_) => {}
+            None => return Err("expected a struct, enum or union".into()),
+        }
+    };
+    if let Some(TokenTree::Punct(p)) = tokens.peek() {
+        if p.as_char() == '<' {
+            return Err("`Singleton` can't be derived for generic types".into());
+        }
+    }
+    let constructor = constructor.unwrap_or_else(|| "::core::default::Default::default".into());
+    Ok(format!(
+        "impl {name} {{
+            pub fn instance() -> &'static Self {{
+                static INSTANCE: ::once_cell::sync::Lazy<{name}> =
+                    ::once_cell::sync::Lazy::new({constructor});
+                &INSTANCE
+            }}
+        }}",
+        name = name,
+        constructor = constructor,
+    )
+    .parse()
+    .unwrap())
+}
+
+/// The constructor path of a `singleton(constructor = ...)` attribute, or
+/// `None` for other attributes.
+fn singleton_attr(attr: TokenStream) -> Result<Option<String>, String> {
+    let mut tokens = attr.into_iter();
+    match tokens.next() {
+        Some(TokenTree::Ident(ident)) if ident.to_string() == "singleton" => {}
+        _ => return Ok(None),
+    }
+    let args = match tokens.next() {
+        Some(TokenTree::Group(args)) if args.delimiter() == Delimiter::Parenthesis => args,
+        _ => return Err("expected `#[singleton(constructor = path)]`".into()),
+    };
+    let mut args = args.stream().into_iter();
+    match (args.next(), args.next()) {
+        (Some(TokenTree::Ident(key)), Some(TokenTree::Punct(eq)))
+            if key.to_string() == "constructor" && eq.as_char() == '=' => {}
+        _ => return Err("expected `#[singleton(constructor = path)]`".into()),
+    }
+    let path: TokenStream = args.collect();
+    if path.is_empty() {
+        return Err("expected a constructor path".into());
+    }
+    Ok(Some(path.to_string()))
+}
+
+/// Turns `fn name(&self) -> &T { body }` into a getter that evaluates `body`
+/// to a `T` on the first call, and returns the stored value from then on.
+///
+/// The value lives in a field named after the method, or the one named by
+/// `#[once(field = name)]`, which must be a `once_cell` cell of `T`. The
+/// struct has to declare it: an attribute on a method only gets that method,
+/// and can't add a field to the struct.
+#[proc_macro_attribute]
+pub fn once(args: TokenStream, item: TokenStream) -> TokenStream {
+    match expand_once(args, item) {
+        Ok(tokens) => tokens,
+        Err(msg) => format!("::core::compile_error!({:?});", msg)
+            .parse()
+            .unwrap(),
+    }
+}
+
+fn expand_once(args: TokenStream, item: TokenStream) -> Result<TokenStream, String> {
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    let body = match tokens.pop() {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => body,
+        _ => return Err("`#[once]` goes on a method with a body".into()),
+    };
+    let fn_at = tokens
+        .iter()
+        .position(|token| matches!(token, TokenTree::Ident(kw) if kw.to_string() == "fn"))
+        .ok_or("`#[once]` goes on a method")?;
+    let name = match tokens.get(fn_at + 1) {
+        Some(TokenTree::Ident(name)) => name.to_string(),
+        _ => return Err("expected a method name".into()),
+    };
+    let params = match tokens.get(fn_at + 2) {
+        Some(TokenTree::Group(params)) if params.delimiter() == Delimiter::Parenthesis => params,
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            return Err("`#[once]` methods can't be generic".into())
+        }
+        _ => return Err("expected the method's parameters".into()),
+    };
+    let receiver: Vec<String> = params.stream().into_iter().map(|t| t.to_string()).collect();
+    if receiver != ["&", "self"] {
+        return Err("`#[once]` methods take `&self` and nothing else".into());
+    }
+    let ty = match (tokens.get(fn_at + 3), tokens.get(fn_at + 4)) {
+        (Some(TokenTree::Punct(dash)), Some(TokenTree::Punct(gt)))
+            if dash.as_char() == '-' && gt.as_char() == '>' =>
+        {
+            value_type(&tokens[fn_at + 5..])?
+        }
+        _ => return Err("`#[once]` methods return `&T`".into()),
+    };
+    let field = field_arg(args)?.unwrap_or(name);
+    let signature: TokenStream = tokens.into_iter().collect();
+    Ok(format!(
+        "{signature} {{ self.{field}.get_or_init(|| -> {ty} {body}) }}",
+        signature = signature,
+        field = field,
+        ty = ty,
+        body = body,
+    )
+    .parse()
+    .unwrap())
+}
+
+/// `T` from the tokens of a `&T` or `&'a T` return type.
+fn value_type(ret: &[TokenTree]) -> Result<String, String> {
+    let mut ret = ret.iter().peekable();
+    match ret.next() {
+        Some(TokenTree::Punct(amp)) if amp.as_char() == '&' => {}
+        _ => return Err("`#[once]` methods return `&T`".into()),
+    }
+    if let Some(TokenTree::Punct(tick)) = ret.peek() {
+        if tick.as_char() == '\'' {
+            ret.next();
+            ret.next();
+        }
+    }
+    if let Some(TokenTree::Ident(kw)) = ret.peek() {
+        if kw.to_string() == "mut" {
+            return Err("`#[once]` methods return `&T`, not `&mut T`".into());
+        }
+    }
+    let ty: TokenStream = ret.cloned().collect();
+    if ty.is_empty() {
+        return Err("`#[once]` methods return `&T`".into());
+    }
+    Ok(ty.to_string())
+}
+
+/// The field of `#[once(field = name)]`, or `None` for `#[once]`.
+fn field_arg(args: TokenStream) -> Result<Option<String>, String> {
+    let mut args = args.into_iter();
+    let (key, eq, field) = match (args.next(), args.next(), args.next()) {
+        (None, _, _) => return Ok(None),
+        (Some(key), Some(eq), Some(field)) => (key, eq, field),
+        _ => return Err("expected `#[once(field = name)]`".into()),
+    };
+    match (key, eq, field, args.next()) {
+        (TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Ident(field), None)
+            if key.to_string() == "field" && eq.as_char() == '=' =>
+        {
+            Ok(Some(field.to_string()))
+        }
+        _ => Err("expected `#[once(field = name)]`".into()),
+    }
+}
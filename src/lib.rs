@@ -1,18 +1,577 @@
+#![cfg_attr(feature = "may-dangle", feature(dropck_eyepatch))]
+// Show cells and lazies as `Uninit` or their value in debuggers.
+#![debugger_visualizer(natvis_file = "../debug_metadata/once_cell.natvis")]
+#![debugger_visualizer(gdb_script_file = "../debug_metadata/once_cell_pretty_printers.py")]
+
 use std::cell::UnsafeCell;
-mod unsync {
-    use super::UnsafeCell;
+use std::mem::ManuallyDrop;
+
+/// Derives `fn instance() -> &'static Self`, a process-wide instance built
+/// on first use:
+///
+/// ```
+/// use once_cell::Singleton;
+///
+/// #[derive(Singleton)]
+/// #[singleton(constructor = Config::load)]
+/// struct Config {
+///     port: u16,
+/// }
+///
+/// impl Config {
+///     fn load() -> Self {
+///         Config { port: 8080 }
+///     }
+/// }
+///
+/// assert_eq!(Config::instance().port, 8080);
+/// ```
+///
+/// Without a `singleton` attribute the instance comes from `Default`.
+/// Generic types aren't supported, since a static can't be generic:
+///
+/// ```compile_fail
+/// #[derive(Default, once_cell::Singleton)]
+/// struct Pool<T>(Vec<T>);
+/// ```
+#[cfg(feature = "derive")]
+pub use once_cell_derive::Singleton;
+
+/// Attribute macros. These live apart from the crate root, where `once!` is
+/// already taken.
+#[cfg(feature = "macros")]
+pub mod macros {
+    /// Memoizes a `&self` getter in a cell field named after it:
+    ///
+    /// ```
+    /// use once_cell::macros::once;
+    /// use once_cell::unsync::OnceCell;
+    ///
+    /// struct Document {
+    ///     text: String,
+    ///     word_count: OnceCell<usize>,
+    /// }
+    ///
+    /// impl Document {
+    ///     #[once]
+    ///     fn word_count(&self) -> &usize {
+    ///         self.text.split_whitespace().count()
+    ///     }
+    /// }
+    ///
+    /// let doc = Document {
+    ///     text: "to be or not".to_string(),
+    ///     word_count: OnceCell::new(),
+    /// };
+    /// assert_eq!(*doc.word_count(), 4);
+    /// ```
+    ///
+    /// The body evaluates to the value rather than a reference, and runs on
+    /// the first call only. `#[once(field = name)]` picks another field, which
+    /// can be a `sync::OnceCell` just as well. The macro can't declare the
+    /// field itself, since an attribute on a method doesn't get to change the
+    /// struct.
+    ///
+    /// Getters taking arguments would need a cell per argument, and aren't
+    /// supported:
+    ///
+    /// ```compile_fail
+    /// struct Circle {
+    ///     area: once_cell::unsync::OnceCell<f64>,
+    /// }
+    ///
+    /// impl Circle {
+    ///     #[once_cell::macros::once]
+    ///     fn area(&self, radius: f64) -> &f64 {
+    ///         radius * radius * std::f64::consts::PI
+    ///     }
+    /// }
+    /// ```
+    pub use once_cell_derive::once;
+}
+
+// Lets the tests use code generated by `Singleton`, which names the crate.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as once_cell;
+
+/// Common interface of the once cells, for code that is generic over the
+/// cell flavor.
+pub trait OnceInit {
+    type Value;
+
+    fn get(&self) -> Option<&Self::Value>;
+
+    fn set(&self, value: Self::Value) -> Result<(), Self::Value>;
+
+    fn get_or_init<F: FnOnce() -> Self::Value>(&self, f: F) -> &Self::Value;
+}
+
+/// Pads and aligns a value to its own cache line, so that a contended cell
+/// doesn't slow down whatever data would otherwise share the line with it.
+///
+/// ```
+/// use once_cell::{sync::OnceCell, CachePadded};
+///
+/// static CONFIG: CachePadded<OnceCell<String>> = CachePadded::new(OnceCell::new());
+/// assert_eq!(CONFIG.get_or_init(|| String::from("prod")), "prod");
+/// ```
+///
+/// The line is taken to be 128 bytes on x86-64 (whose prefetcher pulls in
+/// pairs of lines) and aarch64, and 64 bytes elsewhere.
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Why `Lazy::try_deref` couldn't produce a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LazyError {
+    /// The initializer panicked, on this thread or another.
+    Poisoned,
+    /// The lazy was forced from its own initializer (`unsync::Lazy` only;
+    /// a `sync::Lazy` deadlocks instead).
+    Reentrant,
+}
+
+impl std::fmt::Display for LazyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LazyError::Poisoned => "Lazy instance has previously been poisoned",
+            LazyError::Reentrant => "Lazy instance forced from its own initializer",
+        })
+    }
+}
+
+impl std::error::Error for LazyError {}
+
+/// A lazy's storage: the closure until it is forced, the value after. The
+/// lazy's state says which field is live.
+union LazyData<T, F> {
+    init: ManuallyDrop<F>,
+    value: ManuallyDrop<T>,
+}
+
+/// Implements `Drop`, with `#[may_dangle]` on every type parameter under the
+/// `may-dangle` feature, so that dropck treats the type like an `Option` of
+/// what it owns. The type must own its parameters through a `PhantomData`,
+/// and `drop` may do nothing with them but drop them.
+macro_rules! drop_may_dangle {
+    (impl<$($param:ident),*> Drop for $ty:ty { $($body:tt)* }) => {
+        #[cfg(not(feature = "may-dangle"))]
+        impl<$($param),*> Drop for $ty {
+            $($body)*
+        }
+
+        // SAFETY:
+        // * see the macro's docs
+        #[cfg(feature = "may-dangle")]
+        unsafe impl<$(#[may_dangle] $param),*> Drop for $ty {
+            $($body)*
+        }
+    };
+}
+
+/// Methods of both modules' `OnceCell`s that are written in terms of their
+/// other methods, and so mean the same for either.
+macro_rules! cell_helpers {
+    () => {
+        /// The value as a slice of zero or one elements, so that generic code
+        /// can treat a cell like an array or a `Vec`.
+        pub fn as_slice(&self) -> &[T] {
+            self.get().map_or(&[], std::slice::from_ref)
+        }
+
+        /// Mutable version of `as_slice`.
+        pub fn as_mut_slice(&mut self) -> &mut [T] {
+            self.get_mut().map_or(&mut [], std::slice::from_mut)
+        }
+
+        /// Applies `f` to the value, if any; never initializes the cell.
+        pub fn map_get<U, F: FnOnce(&T) -> U>(&self, f: F) -> Option<U> {
+            self.get().map(f)
+        }
+
+        /// The value, or `err` for `?` to return if the cell is empty.
+        pub fn ok_or<E>(&self, err: E) -> Result<&T, E> {
+            self.get().ok_or(err)
+        }
+
+        /// Like `ok_or`, but only builds the error if the cell is empty.
+        pub fn ok_or_else<E, F: FnOnce() -> E>(&self, err: F) -> Result<&T, E> {
+            self.get().ok_or_else(err)
+        }
+
+        /// Falls back to `default` while the cell is empty, without storing
+        /// it as `get_or_set` would.
+        pub fn get_or<'a>(&'a self, default: &'a T) -> &'a T {
+            self.get().unwrap_or(default)
+        }
+
+        /// Pinned version of `get`.
+        ///
+        /// The cell never moves its value out through a shared reference, and a
+        /// pinned cell can't be moved or borrowed mutably unless `T: Unpin`, so
+        /// pinning the cell pins the value.
+        pub fn get_pin(self: Pin<&Self>) -> Option<Pin<&T>> {
+            // SAFETY:
+            // * see above, the value stays in place until the cell is dropped
+            self.get_ref()
+                .get()
+                .map(|value| unsafe { Pin::new_unchecked(value) })
+        }
+
+        /// Pinned version of `get_or_init`.
+        ///
+        /// The value returned by `f` is moved into the cell before it gets pinned.
+        pub fn get_or_init_pin<F: FnOnce() -> T>(self: Pin<&Self>, f: F) -> Pin<&T> {
+            // SAFETY:
+            // * same reasoning as `get_pin`
+            unsafe { Pin::new_unchecked(self.get_ref().get_or_init(f)) }
+        }
+
+        /// Like `get_or_init`, but hands `ctx` to `f` instead of making it
+        /// capture it, so `f` can be passed borrows that a capturing closure
+        /// would hold for too long.
+        pub fn get_or_init_with<A, F: FnOnce(A) -> T>(&self, ctx: A, f: F) -> &T {
+            self.get_or_init(|| f(ctx))
+        }
+
+        pub fn get_mut_or_init<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+            enum Void {}
+            match self.get_mut_or_try_init(|| Ok::<_, Void>(f())) {
+                Ok(value) => value,
+                Err(void) => match void {},
+            }
+        }
+
+        /// Leaves the cell empty if `f` fails. With `&mut self` there's no
+        /// other initializer to race with.
+        pub fn get_mut_or_try_init<F, E>(&mut self, f: F) -> Result<&mut T, E>
+        where
+            F: FnOnce() -> Result<T, E>,
+        {
+            if self.get_mut().is_none() {
+                let value = f()?;
+                let _ = self.set(value);
+            }
+            Ok(self.get_mut().unwrap())
+        }
+
+        /// Stores `value` unless the cell is already initialized, in which
+        /// case `value` is dropped.
+        pub fn get_or_set(&self, value: T) -> &T {
+            self.get_or_init(|| value)
+        }
+    };
+}
+
+/// Defines `TryOnceCell` on top of the module's `OnceCell`, with the given
+/// docs: the two modules' cells only differ in how their `OnceCell` waits.
+macro_rules! try_once_cell {
+    ($(#[$attr:meta])*) => {
+        $(#[$attr])*
+        pub struct TryOnceCell<T, E> {
+            inner: OnceCell<Result<T, E>>,
+        }
+
+        impl<T, E> TryOnceCell<T, E> {
+            pub const fn new() -> Self {
+                Self {
+                    inner: OnceCell::new(),
+                }
+            }
+
+            /// `None` until an initializer has run.
+            pub fn get(&self) -> Option<Result<&T, &E>> {
+                self.inner.get().map(Result::as_ref)
+            }
+
+            pub fn get_or_try_init<F: FnOnce() -> Result<T, E>>(&self, f: F) -> Result<&T, &E> {
+                self.inner.get_or_init(f).as_ref()
+            }
+
+            pub fn into_inner(self) -> Option<Result<T, E>> {
+                self.inner.into_inner()
+            }
+        }
+
+        impl<T, E> Default for TryOnceCell<T, E> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    };
+}
+
+/// Runs the block at most once per call site, e.g. to print a deprecation
+/// warning. Evaluates to `true` if this pass ran the block.
+///
+/// Concurrent passes wait for the block to finish, like `std::sync::Once`.
+#[macro_export]
+macro_rules! once {
+    ($($body:tt)*) => {{
+        static ONCE: $crate::sync::OnceCell<()> = $crate::sync::OnceCell::new();
+        ONCE.get_or_init_with_flag(|| { $($body)* }).1
+    }};
+}
+
+/// Declares thread-local lazies:
+///
+/// ```
+/// once_cell::thread_lazy! {
+///     static SCRATCH: Vec<u8> = Vec::with_capacity(4096);
+/// }
+///
+/// SCRATCH.with(|scratch| assert_eq!(scratch.capacity(), 4096));
+/// ```
+///
+/// Each static is a const-initialized `thread_local!` holding an
+/// `unsync::Lazy`; every thread runs the initializer on its first access.
+#[macro_export]
+macro_rules! thread_lazy {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;)*) => {
+        ::std::thread_local! {
+            $(
+                $(#[$attr])*
+                $vis static $name: $crate::unsync::Lazy<$t> =
+                    const { $crate::unsync::Lazy::new(|| $init) };
+            )*
+        }
+    };
+}
+
+/// Declares `sync::Lazy` statics that are forced at program startup, before
+/// `main`, instead of on first access:
+///
+/// ```
+/// once_cell::eager_lazy! {
+///     static TABLE: Vec<u32> = (0..256).map(|i| i * i).collect();
+/// }
+///
+/// assert!(once_cell::sync::Lazy::get(&TABLE).is_some());
+/// ```
+///
+/// Use sites see an ordinary `Lazy`. The initializer runs from the
+/// platform's constructor section (`.init_array`, `__mod_init_func` or
+/// `.CRT$XCU`), where a panic aborts the process and the order between
+/// statics is unspecified; on other targets the static is just forced on
+/// first access.
+#[cfg(feature = "ctor")]
+#[macro_export]
+macro_rules! eager_lazy {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::sync::Lazy<$t> = $crate::sync::Lazy::new(|| $init);
+            $crate::__ctor!({
+                $crate::sync::Lazy::force(&$name);
+            });
+            $crate::__track_lazy!($name);
+        )*
+    };
+}
+
+/// Declares `sync::Lazy` statics that register themselves with
+/// [`prewarm`] at program startup, so that `prewarm::force_all` finds them.
+///
+/// ```
+/// once_cell::prewarm_lazy! {
+///     static TABLE: Vec<u32> = (0..256).map(|i| i * i).collect();
+/// }
+///
+/// once_cell::prewarm::force_all();
+/// assert!(once_cell::sync::Lazy::get(&TABLE).is_some());
+/// ```
+#[cfg(feature = "ctor")]
+#[macro_export]
+macro_rules! prewarm_lazy {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::sync::Lazy<$t> = $crate::sync::Lazy::new(|| $init);
+            $crate::__ctor!({
+                $crate::prewarm::register(&$name);
+            });
+            $crate::__track_lazy!($name);
+        )*
+    };
+}
+
+/// Declares `sync::Lazy` statics that, with the `diagnostics` feature, add
+/// themselves to the list returned by `diagnostics::lazies` at program
+/// startup, named by their path. Without it they are plain lazies, so
+/// libraries can declare them unconditionally.
+///
+/// ```
+/// once_cell::tracked_lazy! {
+///     static HOSTNAME: String = "localhost".to_string();
+/// }
+///
+/// assert_eq!(*HOSTNAME, "localhost");
+/// ```
+///
+/// `eager_lazy!` and `prewarm_lazy!` statics are listed too.
+#[cfg(feature = "ctor")]
+#[macro_export]
+macro_rules! tracked_lazy {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::sync::Lazy<$t> = $crate::sync::Lazy::new(|| $init);
+            $crate::__track_lazy!($name);
+        )*
+    };
+}
+
+/// Registers the lazy `$name` with `diagnostics` at program startup.
+#[cfg(all(feature = "ctor", feature = "diagnostics"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __track_lazy {
+    ($name:ident) => {
+        $crate::__ctor!({
+            $crate::diagnostics::register(
+                ::std::concat!(::std::module_path!(), "::", ::std::stringify!($name)),
+                &$name,
+            );
+        });
+    };
+}
+
+#[cfg(all(feature = "ctor", not(feature = "diagnostics")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __track_lazy {
+    ($name:ident) => {};
+}
+
+/// Adds an item to a [`collect::Collection`] at program startup, from
+/// wherever it is defined.
+///
+/// ```
+/// use once_cell::collect::Collection;
+///
+/// pub struct Codec {
+///     pub name: &'static str,
+/// }
+///
+/// pub static CODECS: Collection<Codec> = Collection::new();
+///
+/// once_cell::submit!(CODECS, Codec { name: "gzip" });
+/// once_cell::submit!(CODECS, Codec { name: "zstd" });
+///
+/// assert_eq!(CODECS.items().len(), 2);
+/// ```
+#[cfg(feature = "ctor")]
+#[macro_export]
+macro_rules! submit {
+    ($collection:path, $item:expr) => {
+        $crate::__ctor!({
+            $collection.submit($item);
+        });
+    };
+}
+
+/// Runs `$body` from the platform's constructor section.
+#[cfg(feature = "ctor")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __ctor {
+    ($body:block) => {
+        const _: () = {
+            #[used]
+            #[cfg_attr(
+                any(target_os = "linux", target_os = "android", target_os = "freebsd"),
+                link_section = ".init_array"
+            )]
+            #[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+            #[cfg_attr(windows, link_section = ".CRT$XCU")]
+            static CTOR: extern "C" fn() = {
+                extern "C" fn ctor() $body
+                ctor
+            };
+        };
+    };
+}
+
+pub mod unsync {
+    use super::{LazyData, LazyError, UnsafeCell};
+    use std::cell::Cell;
+    use std::marker::PhantomData;
+    use std::mem::{self, ManuallyDrop};
+    use std::ops::Deref;
+    use std::pin::Pin;
+    /// A cell that can be written to only once.
+    ///
+    /// # Variance
+    ///
+    /// The cell is invariant in `T`, and has to be: it can be set through a
+    /// shared reference, so a covariant cell would let a shorter-lived value
+    /// be stored where a longer-lived one is read back:
+    ///
+    /// ```compile_fail
+    /// use once_cell::unsync::OnceCell;
+    ///
+    /// fn shorten<'a>(cell: &'a OnceCell<&'static str>) -> &'a OnceCell<&'a str> {
+    ///     cell
+    /// }
+    ///
+    /// let cell = OnceCell::new();
+    /// {
+    ///     let local = String::from("dangling");
+    ///     let _ = shorten(&cell).set(&local);
+    /// }
+    /// let _: &'static str = cell.get().unwrap();
+    /// ```
+    ///
+    /// An owned cell can be shortened by rebuilding it:
+    /// `cell.into_inner().map_or_else(OnceCell::new, OnceCell::with_value)`.
     pub struct OnceCell<T> {
         inner: UnsafeCell<Option<T>>,
     }
 
     impl<T> OnceCell<T> {
-        pub fn new() -> Self {
+        pub const fn new() -> Self {
             Self {
                 inner: UnsafeCell::new(None),
             }
         }
 
-        pub fn get(&self) -> Option<&T> {
+        pub const fn with_value(value: T) -> Self {
+            Self {
+                inner: UnsafeCell::new(Some(value)),
+            }
+        }
+
+        /// Usable in const evaluation, e.g. on a cell built by `with_value`.
+        pub const fn get(&self) -> Option<&T> {
             let ptr = self.inner.get();
             // SAFETY:
             // We're sure that the pointer is valid
@@ -21,99 +580,6188 @@ mod unsync {
             unsafe { &*ptr }.as_ref()
         }
 
+        pub fn get_mut(&mut self) -> Option<&mut T> {
+            self.inner.get_mut().as_mut()
+        }
+
+        cell_helpers!();
+
+        pub fn into_inner(self) -> Option<T> {
+            self.inner.into_inner()
+        }
+
+        /// Moves the value out, leaving the cell empty.
+        pub fn take(&mut self) -> Option<T> {
+            self.inner.get_mut().take()
+        }
+
+        /// Gives up on ever dropping the cell, for a `'static` reference to
+        /// its value.
+        pub fn leak(self: Box<Self>) -> Option<&'static T>
+        where
+            T: 'static,
+        {
+            Box::leak(self).get()
+        }
+
         pub fn set(&self, value: T) -> Result<(), T> {
-            if self.get().is_some() {
-                return Err(value);
+            match self.try_insert(value) {
+                Ok(_) => Ok(()),
+                Err((_, value)) => Err(value),
+            }
+        }
+
+        /// Hands back the stored value directly, so that callers don't need to
+        /// `unwrap` a second `get`.
+        fn try_insert(&self, value: T) -> Result<&T, (&T, T)> {
+            if let Some(old) = self.get() {
+                return Err((old, value));
             }
             // SAFETY:
             // * we have exclusive access. We must write the value
             let r = unsafe { &mut *self.inner.get() };
-            let old = std::mem::replace(r, Some(value));
-            debug_assert!(old.is_none());
-            Ok(())
+            Ok(r.insert(value))
+        }
+
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            match self.try_insert(f()) {
+                Ok(value) => value,
+                // `f` initialized the cell itself through a shared reference.
+                Err(_) => panic!("reentrant init"),
+            }
+        }
+
+        /// Like `get_or_init`, but also tells whether this call ran `f`.
+        pub fn get_or_init_with_flag<F: FnOnce() -> T>(&self, f: F) -> (&T, bool) {
+            let mut initialized = false;
+            let value = self.get_or_init(|| {
+                initialized = true;
+                f()
+            });
+            (value, initialized)
         }
     }
-}
 
-mod sync {
-    use super::UnsafeCell;
-    use std::sync::Once;
+    impl<T> Default for OnceCell<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
 
-    pub struct OnceCell<T> {
-        inner: UnsafeCell<Option<T>>,
-        once: Once,
+    impl<T> super::OnceInit for OnceCell<T> {
+        type Value = T;
+
+        fn get(&self) -> Option<&T> {
+            self.get()
+        }
+
+        fn set(&self, value: T) -> Result<(), T> {
+            self.set(value)
+        }
+
+        fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            self.get_or_init(f)
+        }
     }
 
-    unsafe impl<T> Sync for OnceCell<T> {}
+    /// A value computed by `init` on first access.
+    ///
+    /// Holds either the closure or the value, never both: the closure and
+    /// whatever it captured are dropped as soon as it has run.
+    pub struct Lazy<T, F = fn() -> T> {
+        state: Cell<LazyState>,
+        data: UnsafeCell<LazyData<T, F>>,
+        ghost: PhantomData<(T, F)>,
+    }
 
-    impl<T> OnceCell<T> {
-        pub fn new() -> Self {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum LazyState {
+        /// `data.init` is live.
+        Init,
+        /// The closure has been moved out of `data` and is running, or its
+        /// clone is being taken; nothing is live.
+        Running,
+        /// `data.value` is live.
+        Forced,
+        /// The closure panicked; nothing is live.
+        Poisoned,
+    }
+
+    /// Stores a state when dropped, which covers unwinding.
+    struct SetOnDrop<'a, S: Copy>(&'a Cell<S>, S);
+
+    impl<S: Copy> Drop for SetOnDrop<'_, S> {
+        fn drop(&mut self) {
+            self.0.set(self.1);
+        }
+    }
+
+    impl<T, F> Lazy<T, F> {
+        pub const fn new(init: F) -> Self {
             Self {
-                inner: UnsafeCell::new(None),
-                once: Once::new(),
+                state: Cell::new(LazyState::Init),
+                data: UnsafeCell::new(LazyData {
+                    init: ManuallyDrop::new(init),
+                }),
+                ghost: PhantomData,
             }
         }
 
-        pub fn get(&self) -> Option<&T> {
-            if self.once.is_completed() {
-                unsafe { &(*self.inner.get()) }.as_ref()
+        fn forced(value: T) -> Self {
+            Self {
+                state: Cell::new(LazyState::Forced),
+                data: UnsafeCell::new(LazyData {
+                    value: ManuallyDrop::new(value),
+                }),
+                ghost: PhantomData,
+            }
+        }
+    }
+
+    impl<T, F: FnOnce() -> T> Lazy<T, F> {
+        /// An associated function rather than a method, so that it can't shadow
+        /// a `force` method on `T`.
+        pub fn force(this: &Self) -> &T {
+            match Self::try_deref(this) {
+                Ok(value) => value,
+                Err(LazyError::Reentrant) => panic!("reentrant init"),
+                Err(LazyError::Poisoned) => panic!("Lazy instance has previously been poisoned"),
+            }
+        }
+
+        /// Like `force`, but returns an error instead of panicking if the
+        /// initializer panicked before, or if called from the initializer. A
+        /// panic of the initializer run by this call still propagates.
+        pub fn try_deref(this: &Self) -> Result<&T, LazyError> {
+            match this.state.get() {
+                LazyState::Forced => {}
+                LazyState::Init => {
+                    this.state.set(LazyState::Running);
+                    let poison = SetOnDrop(&this.state, LazyState::Poisoned);
+                    // SAFETY:
+                    // * `Init` means the closure is live, and no references
+                    //   into `data` exist before the lazy is forced
+                    // * the state no longer says `Init`, so the closure isn't
+                    //   read or dropped again
+                    let f = unsafe { ManuallyDrop::take(&mut (*this.data.get()).init) };
+                    let value = f();
+                    mem::forget(poison);
+                    // SAFETY:
+                    // * nothing is live and nothing points into `data`
+                    unsafe { (*this.data.get()).value = ManuallyDrop::new(value) };
+                    this.state.set(LazyState::Forced);
+                }
+                LazyState::Running => return Err(LazyError::Reentrant),
+                LazyState::Poisoned => return Err(LazyError::Poisoned),
+            }
+            Ok(Lazy::get(this).unwrap())
+        }
+
+        /// Like `force`, but takes `&mut` and hands out `&mut T`.
+        pub fn force_mut(this: &mut Self) -> &mut T {
+            Self::force(this);
+            Self::get_mut(this).unwrap()
+        }
+
+        /// A lazy computed from this one's value, forcing this one first when
+        /// it is forced itself.
+        ///
+        /// The returned closure type can't be named, so for statics spell the
+        /// dependency out instead: `Lazy::new(|| f(&*OTHER))`.
+        pub fn and_then<'a, U, G>(this: &'a Self, g: G) -> Lazy<U, impl FnOnce() -> U + 'a>
+        where
+            G: FnOnce(&'a T) -> U + 'a,
+        {
+            Lazy::new(move || g(Lazy::force(this)))
+        }
+
+        /// A lazy pairing the values of two others, forcing both on demand.
+        pub fn zip<'a, U, G>(
+            this: &'a Self,
+            other: &'a Lazy<U, G>,
+        ) -> Lazy<(&'a T, &'a U), impl FnOnce() -> (&'a T, &'a U) + 'a>
+        where
+            G: FnOnce() -> U,
+        {
+            Lazy::new(move || (Lazy::force(this), Lazy::force(other)))
+        }
+    }
+
+    /// Like `force`, these are associated functions so that they can't shadow
+    /// methods of `T`.
+    impl<T, F> Lazy<T, F> {
+        /// Returns `None` if the lazy hasn't been forced yet.
+        pub fn get(this: &Self) -> Option<&T> {
+            if this.state.get() == LazyState::Forced {
+                // SAFETY:
+                // * `Forced` means the value is live, and it is never written
+                //   again through a shared reference
+                Some(unsafe { &(*this.data.get()).value })
             } else {
                 None
             }
         }
 
-        pub fn set(&self, value: T) -> Result<(), T> {
-            if self.once.is_completed() {
+        pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+            if this.state.get() == LazyState::Forced {
+                // SAFETY:
+                // * `Forced` means the value is live
+                Some(unsafe { &mut this.data.get_mut().value })
+            } else {
+                None
+            }
+        }
+
+        /// Installs `value` as if the lazy had computed it, e.g. to inject a
+        /// test double, and drops the closure unrun. Fails if the lazy was
+        /// already forced, or is being forced.
+        pub fn preinit(this: &Self, value: T) -> Result<(), T> {
+            if this.state.get() != LazyState::Init {
                 return Err(value);
             }
-            let mut value = Some(value);
-            self.once.call_once(|| {
-                let inner = unsafe { &mut (*self.inner.get()) };
-                debug_assert!(std::mem::replace(inner, value.take()).is_none());
-            });
+            this.state.set(LazyState::Running);
+            // SAFETY:
+            // * as in `try_deref`: the closure was live, and the state no longer
+            //   says so
+            let f = unsafe { ManuallyDrop::take(&mut (*this.data.get()).init) };
+            // SAFETY:
+            // * nothing is live and nothing points into `data`
+            unsafe { (*this.data.get()).value = ManuallyDrop::new(value) };
+            this.state.set(LazyState::Forced);
+            // Last, in case dropping it looks at the lazy.
+            drop(f);
+            Ok(())
+        }
 
-            match value {
-                None => Ok(()),
-                Some(value) => {
-                    debug_assert!(self.once.is_completed());
-                    Err(value)
+        /// Whether the initializer panicked, so that forcing the lazy again
+        /// would panic too.
+        pub fn is_poisoned(this: &Self) -> bool {
+            this.state.get() == LazyState::Poisoned
+        }
+    }
+
+    drop_may_dangle! {
+        impl<T, F> Drop for Lazy<T, F> {
+            fn drop(&mut self) {
+                let data = self.data.get_mut();
+                // SAFETY:
+                // * the state says which field, if any, is live
+                match self.state.get() {
+                    LazyState::Init => unsafe { ManuallyDrop::drop(&mut data.init) },
+                    LazyState::Forced => unsafe { ManuallyDrop::drop(&mut data.value) },
+                    LazyState::Running | LazyState::Poisoned => {}
                 }
             }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn unsync_works() {
-        let once: unsync::OnceCell<String> = unsync::OnceCell::new();
-        assert!(once.get().is_none());
-        assert!(once.set(String::new()).is_ok());
-        assert!(once.set(String::new()).is_err());
-        assert!(once.get().is_some());
-        assert!(once.get().is_some());
+    impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            Lazy::force(self)
+        }
     }
 
-    #[test]
-    fn sync_works() {
-        use std::sync::Arc;
+    impl<T: Default> Default for Lazy<T> {
+        fn default() -> Self {
+            Lazy::new(T::default)
+        }
+    }
 
-        let once = Arc::new(sync::OnceCell::new());
+    /// Clones the value if the lazy was forced, and the closure otherwise.
+    impl<T: Clone, F: FnOnce() -> T + Clone> Clone for Lazy<T, F> {
+        fn clone(&self) -> Self {
+            match self.state.get() {
+                LazyState::Forced => Self::forced(Lazy::get(self).unwrap().clone()),
+                LazyState::Init => {
+                    // Keeps `F::clone` from forcing the lazy, which would move
+                    // the closure out from under it.
+                    self.state.set(LazyState::Running);
+                    let _restore = SetOnDrop(&self.state, LazyState::Init);
+                    // SAFETY:
+                    // * `Init` means the closure is live, and only `force`
+                    //   moves it out
+                    let init = unsafe { (*(*self.data.get()).init).clone() };
+                    Self::new(init)
+                }
+                // Only reachable from within `init` or `F::clone`.
+                LazyState::Running => panic!("Lazy instance cloned while being initialized"),
+                LazyState::Poisoned => panic!("Lazy instance has previously been poisoned"),
+            }
+        }
+    }
 
-        let one = Arc::clone(&once);
-        std::thread::spawn(move || {
-            println!("{:?}", one.set(String::from("Hello")));
-        });
+    try_once_cell! {
+        /// A cell for initializers whose failure is permanent: the first
+        /// outcome, `Ok` or `Err`, is cached, so a failed initializer isn't
+        /// rerun on every access.
+        ///
+        /// Like [`OnceCell::get_or_init`], `get_or_try_init` panics if its
+        /// initializer initialized the cell itself.
+    }
 
-        let two = Arc::clone(&once);
-        std::thread::spawn(move || {
-            println!("{:?}", two.set(String::from("World")));
-        });
+    /// An iterator whose items are computed on demand and kept, so that
+    /// indexing and repeated iteration don't recompute them.
+    ///
+    /// Items live in chunks of doubling size that never reallocate, so
+    /// references to them stay valid while more are computed. The iterator
+    /// may read earlier items of the list itself, but asking for an item it
+    /// hasn't produced yet panics.
+    pub struct LazyList<T, I> {
+        iter: UnsafeCell<Option<I>>,
+        chunks: UnsafeCell<Vec<Vec<T>>>,
+        len: Cell<usize>,
+        running: Cell<bool>,
+    }
 
-        std::thread::sleep(std::time::Duration::from_millis(10));
+    impl<T, I: Iterator<Item = T>> LazyList<T, I> {
+        pub const fn new(iter: I) -> Self {
+            Self {
+                iter: UnsafeCell::new(Some(iter)),
+                chunks: UnsafeCell::new(Vec::new()),
+                len: Cell::new(0),
+                running: Cell::new(false),
+            }
+        }
 
-        println!("{:?}", once.get());
+        /// Item `n`, advancing the iterator as far as needed. `None` if it
+        /// ends first.
+        pub fn get(&self, n: usize) -> Option<&T> {
+            while self.len.get() <= n {
+                let next = {
+                    assert!(!self.running.replace(true), "reentrant LazyList iteration");
+                    let _done = SetOnDrop(&self.running, false);
+                    // SAFETY:
+                    // * `running` keeps anyone else from touching the iterator
+                    unsafe { &mut *self.iter.get() }.as_mut()?.next()
+                };
+                match next {
+                    Some(value) => self.push(value),
+                    // SAFETY:
+                    // * as above, and the iterator is done running
+                    None => unsafe { *self.iter.get() = None },
+                }
+            }
+            // SAFETY:
+            // * items below `len` are written and never moved or dropped
+            //   while the list is borrowed
+            Some(unsafe { &*self.slot(n) })
+        }
+
+        /// How many items have been computed so far.
+        pub fn computed_len(&self) -> usize {
+            self.len.get()
+        }
+
+        /// Iterates over the computed items, then computes the rest.
+        pub fn iter(&self) -> Iter<'_, T, I> {
+            Iter {
+                list: self,
+                next: 0,
+            }
+        }
+
+        fn push(&self, value: T) {
+            let n = self.len.get();
+            // SAFETY:
+            // * nothing else borrows the chunk list itself; references to
+            //   items point into the chunks' buffers, which this leaves in
+            //   place
+            let chunks = unsafe { &mut *self.chunks.get() };
+            let (chunk, _) = Self::locate(n);
+            if chunk == chunks.len() {
+                chunks.push(Vec::with_capacity(1 << chunk));
+            }
+            // Never beyond the capacity, so the buffer never moves.
+            chunks[chunk].push(value);
+            self.len.set(n + 1);
+        }
+
+        /// Chunk `k` holds `2^k` items, starting at item `2^k - 1`.
+        fn locate(n: usize) -> (usize, usize) {
+            let chunk = (usize::BITS - 1 - (n + 1).leading_zeros()) as usize;
+            (chunk, n + 1 - (1 << chunk))
+        }
+
+        /// # Safety
+        ///
+        /// `n` is below `len`.
+        unsafe fn slot(&self, n: usize) -> *const T {
+            let (chunk, i) = Self::locate(n);
+            // SAFETY:
+            // * the caller's contract makes both indices valid
+            unsafe {
+                let chunks = &*self.chunks.get();
+                chunks.get_unchecked(chunk).as_ptr().add(i)
+            }
+        }
+    }
+
+    impl<T, I: Iterator<Item = T>> std::ops::Index<usize> for LazyList<T, I> {
+        type Output = T;
+
+        fn index(&self, n: usize) -> &T {
+            match self.get(n) {
+                Some(value) => value,
+                None => panic!("index {} out of range for LazyList", n),
+            }
+        }
+    }
+
+    impl<'a, T, I: Iterator<Item = T>> IntoIterator for &'a LazyList<T, I> {
+        type Item = &'a T;
+        type IntoIter = Iter<'a, T, I>;
+
+        fn into_iter(self) -> Iter<'a, T, I> {
+            self.iter()
+        }
+    }
+
+    /// See [`LazyList::iter`].
+    pub struct Iter<'a, T, I> {
+        list: &'a LazyList<T, I>,
+        next: usize,
+    }
+
+    impl<'a, T, I: Iterator<Item = T>> Iterator for Iter<'a, T, I> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<&'a T> {
+            let value = self.list.get(self.next)?;
+            self.next += 1;
+            Some(value)
+        }
+    }
+}
+
+pub mod sync {
+    use super::{LazyData, LazyError, UnsafeCell};
+    use std::any::Any;
+    use std::borrow::Borrow;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::hash::Hash;
+    use std::marker::PhantomData;
+    use std::mem::{ManuallyDrop, MaybeUninit};
+    use std::num::NonZeroUsize;
+    use std::ops::Deref;
+    use std::pin::Pin;
+    #[cfg(feature = "metrics")]
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+    use std::sync::{Condvar, Mutex, PoisonError};
+    use std::time::{Duration, Instant};
+
+    /// The blocking primitive behind [`OnceCell`].
+    ///
+    /// The default is [`DefaultBackend`]. Platforms with their own notion of
+    /// waiting (an RTOS, a custom scheduler) can plug in an implementation of
+    /// this trait instead. Implementations should block waiters rather than
+    /// spin, since initializers may run for a long time.
+    ///
+    /// # Safety
+    ///
+    /// The cell relies on the backend for memory safety:
+    /// * `call_once` must run at most one closure to completion over the
+    ///   lifetime of the backend, and must not return before such a run has
+    ///   finished (on this or another thread)
+    /// * `is_completed` must only return `true` after that run has finished
+    /// * finishing the run must synchronize-with (release/acquire) every
+    ///   `is_completed` returning `true` and every `call_once` returning
+    /// * `call_once_timeout` must behave like `call_once`, except that it may
+    ///   return `false`, without having run `f`, if another thread's run
+    ///   doesn't finish within `timeout`
+    /// * `try_call_once` must behave like `call_once`, except that it may
+    ///   return `false`, without having run `f`, instead of panicking because
+    ///   a run panicked
+    pub unsafe trait OnceBackend {
+        fn is_completed(&self) -> bool;
+
+        fn call_once<F: FnOnce()>(&self, f: F);
+
+        /// Returns `false` if the backend is poisoned, where `call_once`
+        /// would panic. The default calls `call_once`, and so panics.
+        fn try_call_once<F: FnOnce()>(&self, f: F) -> bool {
+            self.call_once(f);
+            true
+        }
+
+        /// Returns whether the run finished. The timeout bounds waiting for
+        /// other threads only; `f` itself is never interrupted.
+        fn call_once_timeout<F: FnOnce()>(&self, f: F, timeout: Duration) -> bool;
+
+        /// Best-effort snapshot, for monitoring only. It may be stale by the
+        /// time the caller looks at it.
+        fn state(&self) -> State;
+    }
+
+    /// Returned by [`OnceCell::get_or_init_timeout`] when another thread's
+    /// initializer took too long.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Timeout;
+
+    impl fmt::Display for Timeout {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("timed out waiting for OnceCell initialization")
+        }
+    }
+
+    impl std::error::Error for Timeout {}
+
+    /// A snapshot of a cell's initialization metrics.
+    #[cfg(feature = "metrics")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Metrics {
+        /// Calls that found the cell empty but didn't get to run their own
+        /// initializer, because another thread was already running one.
+        pub contended: usize,
+        /// Wall time of the initializer, if the cell was initialized by one
+        /// rather than by `set`.
+        pub init_time: Option<Duration>,
+    }
+
+    #[cfg(feature = "metrics")]
+    struct InitMetrics {
+        contended: AtomicUsize,
+        /// `u64::MAX` until an initializer finished.
+        init_nanos: AtomicU64,
+    }
+
+    #[cfg(feature = "metrics")]
+    impl InitMetrics {
+        const fn new() -> Self {
+            Self {
+                contended: AtomicUsize::new(0),
+                init_nanos: AtomicU64::new(u64::MAX),
+            }
+        }
+
+        fn record_init(&self, elapsed: Duration) {
+            let nanos = elapsed.as_nanos().min(u128::from(u64::MAX - 1)) as u64;
+            self.init_nanos.store(nanos, Ordering::Relaxed);
+        }
+
+        fn snapshot(&self) -> Metrics {
+            let nanos = self.init_nanos.load(Ordering::Relaxed);
+            Metrics {
+                contended: self.contended.load(Ordering::Relaxed),
+                init_time: if nanos == u64::MAX {
+                    None
+                } else {
+                    Some(Duration::from_nanos(nanos))
+                },
+            }
+        }
+    }
+
+    /// A thread that has been blocked on a cell for longer than the watchdog
+    /// threshold, as passed to the function set with [`set_watchdog`].
+    #[cfg(feature = "watchdog")]
+    #[derive(Debug)]
+    pub struct StuckWait {
+        /// Address of the cell's backend, to tell cells apart.
+        pub cell: usize,
+        pub waiter: std::thread::Thread,
+        /// The thread running the initializer.
+        pub initializer: Option<std::thread::Thread>,
+        pub waited: Duration,
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[derive(Clone, Copy)]
+    struct Watchdog {
+        threshold: Duration,
+        report: fn(&StuckWait),
+    }
+
+    #[cfg(feature = "watchdog")]
+    static WATCHDOG: Mutex<Watchdog> = Mutex::new(Watchdog {
+        threshold: Duration::from_secs(10),
+        report: report_stuck_wait,
+    });
+
+    #[cfg(feature = "watchdog")]
+    fn report_stuck_wait(stuck: &StuckWait) {
+        eprintln!(
+            "once_cell: thread {:?} has been waiting {:?} for the cell at {:#x}, initialized by thread {:?}",
+            stuck.waiter.name().unwrap_or("<unnamed>"),
+            stuck.waited,
+            stuck.cell,
+            stuck.initializer.as_ref().map(|t| t.name().unwrap_or("<unnamed>")),
+        );
+    }
+
+    /// Sets how long a thread may wait for another thread's initializer
+    /// before `report` is called, once per wait. The default reports to
+    /// stderr after 10 seconds; pass a function that forwards to `log` or
+    /// `tracing` to route the report elsewhere.
+    ///
+    /// Applies to cells using the default backend, and to lazies.
+    #[cfg(feature = "watchdog")]
+    pub fn set_watchdog(threshold: Duration, report: fn(&StuckWait)) {
+        *WATCHDOG.lock().unwrap_or_else(PoisonError::into_inner) = Watchdog { threshold, report };
+    }
+
+    /// A sync lazy forced from a thread of an async runtime, where blocking
+    /// stalls every task on it; passed to the function set with
+    /// [`set_async_check`].
+    #[cfg(feature = "diagnostics")]
+    #[derive(Debug)]
+    pub struct AsyncForce {
+        /// Where the lazy was created.
+        pub defined_at: &'static std::panic::Location<'static>,
+        pub forced_at: &'static std::panic::Location<'static>,
+        pub thread: std::thread::Thread,
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[derive(Clone, Copy)]
+    struct AsyncCheck {
+        on_async_worker: fn() -> bool,
+        report: fn(&AsyncForce),
+    }
+
+    #[cfg(feature = "diagnostics")]
+    static ASYNC_CHECK: Mutex<AsyncCheck> = Mutex::new(AsyncCheck {
+        on_async_worker: || false,
+        report: report_async_force,
+    });
+
+    #[cfg(feature = "diagnostics")]
+    fn report_async_force(force: &AsyncForce) {
+        eprintln!(
+            "once_cell: Lazy created at {} is forced at {} on async worker thread {:?}, \
+             blocking it until initialized",
+            force.defined_at,
+            force.forced_at,
+            force.thread.name().unwrap_or("<unnamed>"),
+        );
+    }
+
+    /// Makes forcing a `Lazy` that isn't initialized yet call `report` if
+    /// `on_async_worker` says the current thread belongs to an async
+    /// runtime, e.g. `|| tokio::runtime::Handle::try_current().is_ok()`.
+    /// Until this is called no thread counts as one. The default `report`
+    /// prints to stderr.
+    ///
+    /// Only forces that run the initializer or wait for it are checked;
+    /// forcing an initialized lazy never is.
+    #[cfg(feature = "diagnostics")]
+    pub fn set_async_check(on_async_worker: fn() -> bool, report: fn(&AsyncForce)) {
+        *ASYNC_CHECK.lock().unwrap_or_else(PoisonError::into_inner) = AsyncCheck {
+            on_async_worker,
+            report,
+        };
+    }
+
+    /// An initializer of a sync cell or lazy starting or ending, as passed to
+    /// the function set with [`set_observer`].
+    #[cfg(feature = "observe")]
+    #[derive(Debug)]
+    pub struct InitEvent {
+        /// `std::any::type_name` of the cell or lazy.
+        pub cell_type: &'static str,
+        /// Address of the cell or lazy, to tell them apart.
+        pub cell: usize,
+        /// The thread running the initializer.
+        pub thread: std::thread::Thread,
+        pub kind: InitEventKind,
+    }
+
+    #[cfg(feature = "observe")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum InitEventKind {
+        /// The initializer is about to run.
+        Start,
+        /// The initializer returned; its value is stored right after.
+        Finish { elapsed: Duration },
+        /// The initializer panicked, and the panic is propagating.
+        Panic { elapsed: Duration },
+    }
+
+    #[cfg(feature = "observe")]
+    static OBSERVER: Mutex<fn(&InitEvent)> = Mutex::new(|_| {});
+
+    /// Makes `get_or_init` and `Lazy::force` call `observe` before running an
+    /// initializer, and when it returns or panics, e.g. to record a `tracing`
+    /// span. Until this is called nothing is observed; `set` and `preinit` run
+    /// no initializer and never are.
+    ///
+    /// `observe` runs on the initializing thread, which other threads may be
+    /// waiting on, so it should be quick; it must not initialize the cell
+    /// it's called for, which deadlocks like a reentrant `get_or_init`.
+    #[cfg(feature = "observe")]
+    pub fn set_observer(observe: fn(&InitEvent)) {
+        *OBSERVER.lock().unwrap_or_else(PoisonError::into_inner) = observe;
+    }
+
+    /// Runs `f`, the initializer of `cell`, and tells the observer.
+    #[inline]
+    fn observed<C, R>(_cell: &C, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "observe")]
+        {
+            /// Reports the end of the run when dropped, which covers unwinding.
+            struct Report {
+                observe: fn(&InitEvent),
+                cell_type: &'static str,
+                cell: usize,
+                start: Instant,
+                returned: bool,
+            }
+
+            impl Report {
+                fn send(&self, kind: InitEventKind) {
+                    (self.observe)(&InitEvent {
+                        cell_type: self.cell_type,
+                        cell: self.cell,
+                        thread: std::thread::current(),
+                        kind,
+                    });
+                }
+            }
+
+            impl Drop for Report {
+                fn drop(&mut self) {
+                    let elapsed = self.start.elapsed();
+                    self.send(if self.returned {
+                        InitEventKind::Finish { elapsed }
+                    } else {
+                        InitEventKind::Panic { elapsed }
+                    });
+                }
+            }
+
+            let mut report = Report {
+                observe: *OBSERVER.lock().unwrap_or_else(PoisonError::into_inner),
+                cell_type: std::any::type_name::<C>(),
+                cell: _cell as *const C as usize,
+                start: Instant::now(),
+                returned: false,
+            };
+            report.send(InitEventKind::Start);
+            report.start = Instant::now();
+            let value = f();
+            report.returned = true;
+            value
+        }
+        #[cfg(not(feature = "observe"))]
+        f()
+    }
+
+    /// Where and when a cell was initialized, to find out who beat you to it.
+    #[cfg(feature = "init-site")]
+    #[derive(Debug)]
+    pub struct InitSite {
+        pub thread_id: std::thread::ThreadId,
+        pub thread_name: Option<String>,
+        pub at: std::time::SystemTime,
+        /// Always captured, regardless of `RUST_BACKTRACE`.
+        pub backtrace: std::backtrace::Backtrace,
+    }
+
+    /// Returned by [`OnceCell::set_traced`]: the rejected value, and who
+    /// initialized the cell instead.
+    #[cfg(feature = "init-site")]
+    #[derive(Debug)]
+    pub struct SetError<'a, T> {
+        pub value: T,
+        /// `None` if the cell was created full rather than initialized.
+        pub site: Option<&'a InitSite>,
+    }
+
+    #[cfg(feature = "init-site")]
+    impl InitSite {
+        fn capture() -> Self {
+            let thread = std::thread::current();
+            Self {
+                thread_id: thread.id(),
+                thread_name: thread.name().map(String::from),
+                at: std::time::SystemTime::now(),
+                backtrace: std::backtrace::Backtrace::force_capture(),
+            }
+        }
+    }
+
+    /// Where a cell is in its initialization.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum State {
+        /// Nobody has started initializing the cell.
+        Uninit,
+        /// A thread is running the initializer right now.
+        Initializing,
+        /// The cell holds a value.
+        Complete,
+        /// An initializer panicked; the cell will never hold a value.
+        Poisoned,
+    }
+
+    const UNINIT: u8 = 0;
+    const INITIALIZING: u8 = 1;
+    const COMPLETE: u8 = 2;
+    const POISONED: u8 = 3;
+
+    impl State {
+        fn from_word(word: u8) -> Self {
+            match word {
+                UNINIT => State::Uninit,
+                INITIALIZING => State::Initializing,
+                COMPLETE => State::Complete,
+                _ => State::Poisoned,
+            }
+        }
+    }
+
+    mod sealed {
+        pub trait Sealed {}
+    }
+
+    /// How a [`PolicyBackend`] waits for another thread's initializer:
+    /// [`Park`] or [`Spin`].
+    pub trait WaitPolicy: sealed::Sealed {
+        #[doc(hidden)]
+        const SPIN: bool;
+    }
+
+    /// What a [`PolicyBackend`] does when an initializer panics:
+    /// [`Poison`] or [`Retry`].
+    pub trait PanicPolicy: sealed::Sealed {
+        #[doc(hidden)]
+        const RETRY: bool;
+    }
+
+    /// Waiters sleep until the initializer finishes.
+    #[derive(Debug)]
+    pub enum Park {}
+
+    /// Waiters spin, yielding to the scheduler, for initializers known to
+    /// be very short.
+    #[derive(Debug)]
+    pub enum Spin {}
+
+    /// A panicking initializer poisons the cell for good.
+    #[derive(Debug)]
+    pub enum Poison {}
+
+    /// A panicking initializer leaves the cell empty, and the next caller
+    /// (or a waiter) runs its own.
+    #[derive(Debug)]
+    pub enum Retry {}
+
+    impl sealed::Sealed for Park {}
+    impl sealed::Sealed for Spin {}
+    impl sealed::Sealed for Poison {}
+    impl sealed::Sealed for Retry {}
+
+    impl WaitPolicy for Park {
+        const SPIN: bool = false;
+    }
+
+    impl WaitPolicy for Spin {
+        const SPIN: bool = true;
+    }
+
+    impl PanicPolicy for Poison {
+        const RETRY: bool = false;
+    }
+
+    impl PanicPolicy for Retry {
+        const RETRY: bool = true;
+    }
+
+    /// A state word for the fast path, plus a mutex and condition variable that
+    /// threads park on while someone else runs the initializer.
+    ///
+    /// With [`Park`], waiting never spins: a thread that finds the cell
+    /// `Initializing` blocks on the condition variable, which is the
+    /// platform's wait queue (a futex on Linux), and uses no CPU until the
+    /// initializer finishes, panics, or a timed wait's deadline passes. The
+    /// initializer is not run under the mutex, which is only taken briefly to
+    /// wait and to notify.
+    ///
+    /// The type parameters pick the waiting and panic handling at compile
+    /// time:
+    ///
+    /// ```
+    /// use once_cell::sync::{OnceCell, PolicyBackend, Retry, Spin};
+    ///
+    /// static HOSTNAME: OnceCell<String, PolicyBackend<Spin, Retry>> =
+    ///     OnceCell::with_backend(PolicyBackend::new());
+    ///
+    /// assert_eq!(HOSTNAME.get_or_init(|| "localhost".to_string()), "localhost");
+    /// ```
+    ///
+    /// [`Lazy::with_backend`] takes a policy too, but as it consumes its
+    /// closure, a panic poisons it even with [`Retry`].
+    ///
+    /// Racing, where every contender runs its initializer and the first to
+    /// finish wins, isn't a third [`WaitPolicy`]: a backend runs at most one
+    /// closure to completion, and that is what lets the cell write its slot
+    /// without a lock. Racers run their initializers outside the backend and
+    /// only store through it, which is a choice per call,
+    /// [`OnceCell::get_or_init_racy`]. The `race` module drops the backend
+    /// too, publishing with a compare-and-swap on a one-word slot, so that it
+    /// never blocks at all.
+    pub struct PolicyBackend<W: WaitPolicy = Park, P: PanicPolicy = Poison> {
+        state: AtomicU8,
+        lock: Mutex<()>,
+        cvar: Condvar,
+        #[cfg(feature = "watchdog")]
+        initializer: Mutex<Option<std::thread::Thread>>,
+        policy: PhantomData<(W, P)>,
+    }
+
+    /// `std::thread::yield_now`, but through the C call where there is one,
+    /// which can't unwind, so that spinning adds no unwind path.
+    #[inline]
+    fn yield_now() {
+        #[cfg(unix)]
+        {
+            extern "C" {
+                fn sched_yield() -> std::os::raw::c_int;
+            }
+            // SAFETY:
+            // * `sched_yield` has no preconditions
+            unsafe { sched_yield() };
+        }
+        #[cfg(not(unix))]
+        std::thread::yield_now();
+    }
+
+    /// The backend cells use unless told otherwise: waiters park, and a
+    /// panicking initializer poisons the cell.
+    pub type DefaultBackend = PolicyBackend<Park, Poison>;
+
+    impl<W: WaitPolicy, P: PanicPolicy> PolicyBackend<W, P> {
+        pub const fn new() -> Self {
+            Self::with_state(UNINIT)
+        }
+
+        const fn with_state(state: u8) -> Self {
+            Self {
+                state: AtomicU8::new(state),
+                lock: Mutex::new(()),
+                cvar: Condvar::new(),
+                #[cfg(feature = "watchdog")]
+                initializer: Mutex::new(None),
+                policy: PhantomData,
+            }
+        }
+
+        /// Returns `false` if `deadline` passed while the run was in progress.
+        fn wait(&self, deadline: Option<Instant>) -> bool {
+            if W::SPIN {
+                while self.state.load(Ordering::Acquire) == INITIALIZING {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return false;
+                    }
+                    yield_now();
+                }
+                return true;
+            }
+            #[cfg(feature = "watchdog")]
+            let (started, mut watchdog) = (Instant::now(), true);
+            let mut guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+            // Checked under the lock, and `Finish` takes the lock before
+            // notifying, so the wakeup can't be missed.
+            while self.state.load(Ordering::Acquire) == INITIALIZING {
+                let now = Instant::now();
+                if deadline.is_some_and(|deadline| now >= deadline) {
+                    return false;
+                }
+                let wake_at = deadline;
+                #[cfg(feature = "watchdog")]
+                let wake_at = if watchdog {
+                    let Watchdog { threshold, report } =
+                        *WATCHDOG.lock().unwrap_or_else(PoisonError::into_inner);
+                    let warn_at = started + threshold;
+                    if now < warn_at {
+                        Some(wake_at.map_or(warn_at, |deadline| deadline.min(warn_at)))
+                    } else {
+                        watchdog = false;
+                        let stuck = StuckWait {
+                            cell: self as *const Self as usize,
+                            waiter: std::thread::current(),
+                            initializer: self
+                                .initializer
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .clone(),
+                            waited: now - started,
+                        };
+                        // Not under the lock, which would hold up the
+                        // initializer's wakeup.
+                        drop(guard);
+                        report(&stuck);
+                        guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+                        continue;
+                    }
+                } else {
+                    wake_at
+                };
+                guard = match wake_at {
+                    None => self
+                        .cvar
+                        .wait(guard)
+                        .unwrap_or_else(PoisonError::into_inner),
+                    Some(wake_at) => {
+                        self.cvar
+                            .wait_timeout(guard, wake_at.saturating_duration_since(now))
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .0
+                    }
+                };
+            }
+            true
+        }
+
+        fn run<F: FnOnce()>(&self, f: F, deadline: Option<Instant>) -> bool {
+            match self.try_run(f, deadline) {
+                Some(completed) => completed,
+                None => panic!("OnceCell initializer panicked, the cell is poisoned"),
+            }
+        }
+
+        /// Like `run`, but returns `None` instead of panicking if the cell is
+        /// poisoned, including by the run this call waited for.
+        fn try_run<F: FnOnce()>(&self, f: F, deadline: Option<Instant>) -> Option<bool> {
+            loop {
+                match self.state.compare_exchange(
+                    UNINIT,
+                    INITIALIZING,
+                    Ordering::Acquire,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => {
+                        #[cfg(feature = "watchdog")]
+                        {
+                            *self
+                                .initializer
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner) =
+                                Some(std::thread::current());
+                        }
+                        let mut finish = Finish {
+                            backend: self,
+                            state: if P::RETRY { UNINIT } else { POISONED },
+                        };
+                        f();
+                        finish.state = COMPLETE;
+                        return Some(true);
+                    }
+                    Err(COMPLETE) => return Some(true),
+                    // With `Retry` nothing stores `POISONED`, and the guard
+                    // lets `run` compile without its panic.
+                    Err(POISONED) if !P::RETRY => return None,
+                    Err(_) => {
+                        if !self.wait(deadline) {
+                            return Some(false);
+                        }
+                    }
+                }
+            }
+        }
+
+        /// Takes the state out of `UNINIT` without running anything, so that
+        /// other threads wait; dropping the guard puts it back. Returns the
+        /// current state if it isn't `UNINIT`.
+        fn claim(&self) -> Result<Finish<'_, W, P>, u8> {
+            self.state
+                .compare_exchange(UNINIT, INITIALIZING, Ordering::Acquire, Ordering::Acquire)
+                .map(|_| Finish {
+                    backend: self,
+                    state: UNINIT,
+                })
+        }
+    }
+
+    impl<W: WaitPolicy, P: PanicPolicy> Default for PolicyBackend<W, P> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// Publishes the outcome of a run and wakes the waiters, even if the
+    /// initializer unwinds.
+    struct Finish<'a, W: WaitPolicy, P: PanicPolicy> {
+        backend: &'a PolicyBackend<W, P>,
+        state: u8,
+    }
+
+    impl<W: WaitPolicy, P: PanicPolicy> Drop for Finish<'_, W, P> {
+        fn drop(&mut self) {
+            self.backend.state.store(self.state, Ordering::Release);
+            if !W::SPIN {
+                let _guard = self
+                    .backend
+                    .lock
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                self.backend.cvar.notify_all();
+            }
+        }
+    }
+
+    // SAFETY:
+    // * only the thread that moves the state out of `UNINIT` runs its closure,
+    //   and once a closure returned the state never goes back; `Retry` only
+    //   reopens the cell after a run that panicked, and `claim` runs none
+    // * `COMPLETE` is stored with `Release` after the closure returned, and
+    //   every check for it is an `Acquire` load
+    // * `call_once` only returns once the state is `COMPLETE`, or by panicking;
+    //   `call_once_timeout` returns `false` instead when the deadline passes,
+    //   and `try_call_once` when the state is `POISONED`
+    unsafe impl<W: WaitPolicy, P: PanicPolicy> OnceBackend for PolicyBackend<W, P> {
+        #[inline]
+        fn is_completed(&self) -> bool {
+            self.state.load(Ordering::Acquire) == COMPLETE
+        }
+
+        fn call_once<F: FnOnce()>(&self, f: F) {
+            self.run(f, None);
+        }
+
+        fn try_call_once<F: FnOnce()>(&self, f: F) -> bool {
+            self.try_run(f, None).is_some()
+        }
+
+        fn call_once_timeout<F: FnOnce()>(&self, f: F, timeout: Duration) -> bool {
+            // A deadline too far out to represent is as good as none.
+            self.run(f, Instant::now().checked_add(timeout))
+        }
+
+        fn state(&self) -> State {
+            State::from_word(self.state.load(Ordering::Acquire))
+        }
+    }
+
+    /// A backend on the platform's own once primitive, `pthread_once`, so
+    /// that waiting shows up as such to OS tooling.
+    ///
+    /// Unlike with [`DefaultBackend`], `call_once_timeout` can't give up
+    /// early: `pthread_once` has no timed wait, so it waits like `call_once`.
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    pub struct NativeBackend {
+        once: UnsafeCell<std::os::raw::c_int>,
+        /// Mirrors the run, since `pthread_once_t` can't be inspected; also
+        /// records poisoning, as a panicking run still completes the once.
+        state: AtomicU8,
+    }
+
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    mod native {
+        use std::cell::Cell;
+        use std::os::raw::c_int;
+
+        extern "C" {
+            pub(super) fn pthread_once(once: *mut c_int, init: extern "C" fn()) -> c_int;
+        }
+
+        /// `PTHREAD_ONCE_INIT` on glibc, musl and bionic.
+        pub(super) const PTHREAD_ONCE_INIT: c_int = 0;
+
+        thread_local! {
+            /// `pthread_once` passes nothing to its routine, so the closure to
+            /// run is handed over through the calling thread.
+            pub(super) static RUN: Cell<Option<*mut dyn FnMut()>> = const { Cell::new(None) };
+        }
+
+        pub(super) extern "C" fn run() {
+            if let Some(run) = RUN.with(Cell::take) {
+                // SAFETY:
+                // * set by `call_once` on this thread, which is still inside
+                //   `pthread_once` and keeps the closure alive; it catches
+                //   panics, so nothing unwinds into C
+                unsafe { (*run)() }
+            }
+        }
+    }
+
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    impl NativeBackend {
+        pub const fn new() -> Self {
+            Self {
+                once: UnsafeCell::new(native::PTHREAD_ONCE_INIT),
+                state: AtomicU8::new(UNINIT),
+            }
+        }
+    }
+
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    impl Default for NativeBackend {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    // SAFETY:
+    // * the once is only touched through `pthread_once`, which is thread-safe
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    unsafe impl Sync for NativeBackend {}
+
+    // SAFETY:
+    // * `pthread_once` runs its routine at most once, and returns only after
+    //   it finished, on whichever thread
+    // * `COMPLETE` is stored with `Release` after the closure returned, and
+    //   every check for it is an `Acquire` load
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    unsafe impl OnceBackend for NativeBackend {
+        #[inline]
+        fn is_completed(&self) -> bool {
+            self.state.load(Ordering::Acquire) == COMPLETE
+        }
+
+        fn call_once<F: FnOnce()>(&self, f: F) {
+            if self.is_completed() {
+                return;
+            }
+            let mut f = Some(f);
+            let mut panic = None;
+            {
+                let mut run = || {
+                    self.state.store(INITIALIZING, Ordering::Relaxed);
+                    let f = f.take().unwrap();
+                    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+                        Ok(()) => self.state.store(COMPLETE, Ordering::Release),
+                        Err(payload) => {
+                            self.state.store(POISONED, Ordering::Release);
+                            panic = Some(payload);
+                        }
+                    }
+                };
+                let run: &mut dyn FnMut() = &mut run;
+                // SAFETY:
+                // * only erases the lifetime; `native::run` calls it before
+                //   `pthread_once` returns, and the slot is cleared below
+                let run: *mut (dyn FnMut() + 'static) = unsafe { std::mem::transmute(run) };
+                native::RUN.with(|slot| slot.set(Some(run)));
+                // SAFETY:
+                // * the once lives in `self`, and is only used by this call
+                let rc = unsafe { native::pthread_once(self.once.get(), native::run) };
+                // Left over if another thread ran the routine.
+                native::RUN.with(|slot| slot.set(None));
+                assert_eq!(rc, 0, "pthread_once failed");
+            }
+            if let Some(payload) = panic {
+                std::panic::resume_unwind(payload);
+            }
+            if self.state.load(Ordering::Acquire) == POISONED {
+                panic!("OnceCell initializer panicked, the cell is poisoned");
+            }
+        }
+
+        fn call_once_timeout<F: FnOnce()>(&self, f: F, _timeout: Duration) -> bool {
+            self.call_once(f);
+            true
+        }
+
+        fn state(&self) -> State {
+            State::from_word(self.state.load(Ordering::Acquire))
+        }
+    }
+
+    /// A lock that blocks, such as an RTOS mutex with priority inheritance
+    /// (a FreeRTOS `xSemaphoreCreateMutex`, say), for [`LockBackend`].
+    ///
+    /// # Safety
+    ///
+    /// * `lock` and a successful `try_lock_for` must not return while another
+    ///   caller holds the lock
+    /// * `unlock` must synchronize-with (release/acquire) the next
+    ///   acquisition
+    pub unsafe trait RawLock {
+        fn lock(&self);
+
+        /// Returns `false`, without taking the lock, if it wasn't available
+        /// within `timeout`.
+        fn try_lock_for(&self, timeout: Duration) -> bool;
+
+        /// Only called by the holder of the lock.
+        fn unlock(&self);
+    }
+
+    /// A backend that runs the initializer while holding a lock, which
+    /// waiters block on.
+    ///
+    /// With a priority-inheritance mutex this avoids priority inversion: a
+    /// high-priority task waiting for a low-priority initializer lends it
+    /// its priority, which a backend that parks waiters can't do. As with
+    /// any non-recursive lock, initializing the cell again from its own
+    /// initializer deadlocks.
+    pub struct LockBackend<L> {
+        lock: L,
+        state: AtomicU8,
+    }
+
+    impl<L> LockBackend<L> {
+        pub const fn new(lock: L) -> Self {
+            Self {
+                lock,
+                state: AtomicU8::new(UNINIT),
+            }
+        }
+    }
+
+    impl<L: RawLock> LockBackend<L> {
+        /// Must be called with the lock held; unlocks it.
+        fn run_locked<F: FnOnce()>(&self, f: F) {
+            struct Unlock<'a, L: RawLock> {
+                backend: &'a LockBackend<L>,
+                state: u8,
+            }
+
+            impl<L: RawLock> Drop for Unlock<'_, L> {
+                fn drop(&mut self) {
+                    if self.state != UNINIT {
+                        self.backend.state.store(self.state, Ordering::Release);
+                    }
+                    self.backend.lock.unlock();
+                }
+            }
+
+            let mut unlock = Unlock {
+                backend: self,
+                state: UNINIT,
+            };
+            match self.state.load(Ordering::Acquire) {
+                UNINIT => {
+                    self.state.store(INITIALIZING, Ordering::Relaxed);
+                    unlock.state = POISONED;
+                    f();
+                    unlock.state = COMPLETE;
+                }
+                COMPLETE => {}
+                _ => panic!("OnceCell initializer panicked, the cell is poisoned"),
+            }
+        }
+    }
+
+    // SAFETY:
+    // * the closure runs under the lock, and only if no run started before
+    // * `COMPLETE` is stored with `Release` after the closure returned, and
+    //   every check for it is an `Acquire` load
+    // * `call_once` only returns after taking the lock, which the run held
+    //   until it finished
+    unsafe impl<L: RawLock> OnceBackend for LockBackend<L> {
+        #[inline]
+        fn is_completed(&self) -> bool {
+            self.state.load(Ordering::Acquire) == COMPLETE
+        }
+
+        fn call_once<F: FnOnce()>(&self, f: F) {
+            if self.is_completed() {
+                return;
+            }
+            self.lock.lock();
+            self.run_locked(f);
+        }
+
+        fn call_once_timeout<F: FnOnce()>(&self, f: F, timeout: Duration) -> bool {
+            if self.is_completed() {
+                return true;
+            }
+            if !self.lock.try_lock_for(timeout) {
+                return self.is_completed();
+            }
+            self.run_locked(f);
+            true
+        }
+
+        fn state(&self) -> State {
+            State::from_word(self.state.load(Ordering::Acquire))
+        }
+    }
+
+    /// A thread-safe cell that can be written to only once.
+    ///
+    /// The value is stored as an `Option<T>`, so there is no slot to fill in
+    /// through a raw pointer; `raw::RawOnceCell::as_ptr` offers that for FFI.
+    ///
+    /// # Panicking initializers
+    ///
+    /// If an initializer panics, the panic propagates out of the call that ran
+    /// it and the cell is poisoned for good; it is never retried, because a
+    /// half-finished initializer may have left outside state it depends on
+    /// inconsistent. With the default backend:
+    /// * threads waiting for that initializer are woken, and each panics with
+    ///   "OnceCell initializer panicked, the cell is poisoned"
+    /// * so does every later `get_or_init`, `get_or_init_timeout` and `set`
+    /// * `get` keeps returning `None`, and `state` returns `State::Poisoned`
+    pub struct OnceCell<T, B = DefaultBackend> {
+        inner: UnsafeCell<Option<T>>,
+        backend: B,
+        #[cfg(feature = "metrics")]
+        metrics: InitMetrics,
+        #[cfg(feature = "init-site")]
+        init_site: UnsafeCell<Option<InitSite>>,
+    }
+
+    // SAFETY:
+    // * sharing the cell shares the value, hence `T: Sync`
+    // * the value may be created on one thread and dropped on another, hence
+    //   `T: Send`
+    unsafe impl<T: Send + Sync, B: Sync> Sync for OnceCell<T, B> {}
+
+    impl<T> OnceCell<T> {
+        pub const fn new() -> Self {
+            Self::with_backend(DefaultBackend::new())
+        }
+    }
+
+    impl<T, B: OnceBackend> OnceCell<T, B> {
+        pub const fn with_backend(backend: B) -> Self {
+            Self {
+                inner: UnsafeCell::new(None),
+                backend,
+                #[cfg(feature = "metrics")]
+                metrics: InitMetrics::new(),
+                #[cfg(feature = "init-site")]
+                init_site: UnsafeCell::new(None),
+            }
+        }
+
+        /// Once the cell is initialized, this is a single `Acquire` load of
+        /// the backend's state (with the default backend), with no locks or
+        /// calls.
+        #[inline]
+        pub fn get(&self) -> Option<&T> {
+            if self.backend.is_completed() {
+                // SAFETY:
+                // * a completed run always stored a value, which is never
+                //   written again
+                Some(unsafe { (*self.inner.get()).as_ref().unwrap_unchecked() })
+            } else {
+                None
+            }
+        }
+
+        /// No synchronization needed: `&mut self` rules out a concurrent run.
+        pub fn get_mut(&mut self) -> Option<&mut T> {
+            self.inner.get_mut().as_mut()
+        }
+
+        pub fn into_inner(self) -> Option<T> {
+            self.inner.into_inner()
+        }
+
+        cell_helpers!();
+
+        /// Gives up on ever dropping the cell, for a `'static` reference to
+        /// its value, e.g. to hand to spawned threads.
+        pub fn leak(self: Box<Self>) -> Option<&'static T>
+        where
+            T: 'static,
+            B: 'static,
+        {
+            Box::leak(self).get()
+        }
+
+        /// Tells "nobody started" apart from "someone is still initializing",
+        /// which `get` can't.
+        pub fn state(&self) -> State {
+            self.backend.state()
+        }
+
+        /// Whether an initializer panicked, so that the cell can never be set.
+        pub fn is_poisoned(&self) -> bool {
+            self.state() == State::Poisoned
+        }
+
+        pub fn set(&self, value: T) -> Result<(), T> {
+            if self.backend.is_completed() {
+                return Err(value);
+            }
+            let mut value = Some(value);
+            // SAFETY:
+            // * the backend runs at most one closure, so `value` is still there
+            self.backend
+                .call_once(|| self.store(unsafe { value.take().unwrap_unchecked() }));
+            match value {
+                None => Ok(()),
+                Some(value) => Err(value),
+            }
+        }
+
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            let mut ran = false;
+            self.backend.call_once(|| {
+                ran = true;
+                self.initialize(f)
+            });
+            self.record_slow_path(ran);
+            // SAFETY:
+            // * `call_once` only returns once the backend completed, i.e. a
+            //   closure stored the value
+            unsafe { self.get().unwrap_unchecked() }
+        }
+
+        /// Like `get_or_init`, but gives up if another thread's initializer
+        /// doesn't finish within `timeout`.
+        ///
+        /// Only waiting is bounded: if this call gets to run `f`, it runs to
+        /// completion however long it takes.
+        pub fn get_or_init_timeout<F: FnOnce() -> T>(
+            &self,
+            f: F,
+            timeout: Duration,
+        ) -> Result<&T, Timeout> {
+            if let Some(value) = self.get() {
+                return Ok(value);
+            }
+            let mut ran = false;
+            let completed = self.backend.call_once_timeout(
+                || {
+                    ran = true;
+                    self.initialize(f)
+                },
+                timeout,
+            );
+            self.record_slow_path(ran);
+            if completed {
+                Ok(self.get().unwrap())
+            } else {
+                Err(Timeout)
+            }
+        }
+
+        /// Must only be called from the backend's `call_once*`.
+        fn initialize<F: FnOnce() -> T>(&self, f: F) {
+            #[cfg(feature = "metrics")]
+            let start = Instant::now();
+            let value = observed(self, f);
+            #[cfg(feature = "metrics")]
+            self.metrics.record_init(start.elapsed());
+            self.store(value);
+        }
+
+        /// Must only be called from the backend's `call_once*`.
+        fn store(&self, value: T) {
+            // SAFETY:
+            // * the backend runs at most one closure, and readers only look at
+            //   the value once the backend is completed
+            unsafe { *self.inner.get() = Some(value) };
+            // SAFETY:
+            // * same as for the value
+            #[cfg(feature = "init-site")]
+            unsafe {
+                *self.init_site.get() = Some(InitSite::capture())
+            };
+        }
+
+        /// Who initialized the cell, and when. `None` while the cell is empty.
+        #[cfg(feature = "init-site")]
+        pub fn init_site(&self) -> Option<&InitSite> {
+            if self.backend.is_completed() {
+                // SAFETY:
+                // * written before the backend completed, never written again
+                unsafe { &*self.init_site.get() }.as_ref()
+            } else {
+                None
+            }
+        }
+
+        /// Like `set`, but the error also says who initialized the cell.
+        #[cfg(feature = "init-site")]
+        pub fn set_traced(&self, value: T) -> Result<(), SetError<'_, T>> {
+            self.set(value).map_err(|value| SetError {
+                value,
+                site: self.init_site(),
+            })
+        }
+
+        /// `ran` tells whether this call got to run its initializer; if it
+        /// didn't, it had to wait for (or lost to) another thread.
+        #[inline]
+        fn record_slow_path(&self, _ran: bool) {
+            #[cfg(feature = "metrics")]
+            if !_ran {
+                self.metrics.contended.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        /// How contended the cell's initialization was, and how long it took.
+        #[cfg(feature = "metrics")]
+        pub fn metrics(&self) -> Metrics {
+            self.metrics.snapshot()
+        }
+
+        /// Like `get_or_init`, but also tells whether this call ran `f`.
+        pub fn get_or_init_with_flag<F: FnOnce() -> T>(&self, f: F) -> (&T, bool) {
+            let mut initialized = false;
+            let value = self.get_or_init(|| {
+                initialized = true;
+                f()
+            });
+            (value, initialized)
+        }
+
+        /// Like `get_or_init`, but runs `f` without holding the backend.
+        ///
+        /// If several threads find the cell empty, all of them run `f`; the first
+        /// value to be stored wins and the others are dropped. Racy callers
+        /// only hold the backend for as long as it takes to move a value in,
+        /// so they never wait on each other's `f`. They do go through the
+        /// backend to store it, though, and so still wait, parked, for a
+        /// concurrent `get_or_init` to finish its initializer. Prefer this when
+        /// `f` is cheap and wasted work is better than waiting.
+        pub fn get_or_init_racy<F: FnOnce() -> T>(&self, f: F) -> &T {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            // Losing the race is fine, the winner's value is just as good.
+            let _ = self.set(f());
+            self.get().unwrap()
+        }
+    }
+
+    impl<T, B: OnceBackend + Default> OnceCell<T, B> {
+        /// Moves the value out, leaving the cell empty, with a fresh backend
+        /// that can be initialized again, even if the old one was poisoned.
+        pub fn take(&mut self) -> Option<T> {
+            std::mem::take(self).into_inner()
+        }
+    }
+
+    impl<T, B: OnceBackend + Default> Default for OnceCell<T, B> {
+        fn default() -> Self {
+            Self::with_backend(B::default())
+        }
+    }
+
+    impl<T, B: OnceBackend> super::OnceInit for OnceCell<T, B> {
+        type Value = T;
+
+        fn get(&self) -> Option<&T> {
+            self.get()
+        }
+
+        fn set(&self, value: T) -> Result<(), T> {
+            self.set(value)
+        }
+
+        fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            self.get_or_init(f)
+        }
+    }
+
+    /// A cell that can be set once with a value of any type, and read back by
+    /// naming that type.
+    pub struct OnceAny {
+        inner: OnceCell<Box<dyn Any + Send + Sync>>,
+    }
+
+    impl OnceAny {
+        pub fn new() -> Self {
+            Self {
+                inner: OnceCell::new(),
+            }
+        }
+
+        pub fn set<T: Any + Send + Sync>(&self, value: T) -> Result<(), T> {
+            self.inner.set(Box::new(value)).map_err(|value| {
+                // We just boxed it, so the type is known.
+                *value.downcast::<T>().unwrap()
+            })
+        }
+
+        /// Returns `None` if the cell is empty or holds a value of another type.
+        pub fn get<T: Any>(&self) -> Option<&T> {
+            self.inner.get()?.downcast_ref()
+        }
+    }
+
+    impl Default for OnceAny {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// A value computed by `init` on first access, shareable between threads.
+    ///
+    /// `Lazy<T, F, B>` is asserted `Sync` when `T: Send + Sync`, `F: Send`
+    /// and `B: Sync`.
+    /// That is sound because whichever thread forces the lazy first takes
+    /// `init` over and runs it, so `init` moves to that thread but is never
+    /// shared, while the value it produces is shared with, and may be dropped
+    /// by, any other thread. The examples below lock the bounds in.
+    ///
+    /// A value that can't be shared:
+    ///
+    /// ```compile_fail
+    /// fn assert_sync<T: Sync>() {}
+    /// assert_sync::<once_cell::sync::Lazy<std::cell::Cell<u32>>>();
+    /// ```
+    ///
+    /// A value that can't leave the thread that created it:
+    ///
+    /// ```compile_fail
+    /// fn assert_sync<T: Sync>() {}
+    /// assert_sync::<once_cell::sync::Lazy<std::sync::MutexGuard<'static, u32>>>();
+    /// ```
+    ///
+    /// An initializer that can't be run on another thread:
+    ///
+    /// ```compile_fail
+    /// fn assert_sync<T: Sync>(_: &T) {}
+    /// let rc = std::rc::Rc::new(92);
+    /// assert_sync(&once_cell::sync::Lazy::new(move || *rc));
+    /// ```
+    pub struct Lazy<T, F = fn() -> T, B = DefaultBackend> {
+        backend: B,
+        data: UnsafeCell<LazyData<T, F>>,
+        /// Which field of `data` is live: `UNINIT` for the closure, `COMPLETE`
+        /// for the value, `POISONED` for neither. Kept apart from the backend,
+        /// which may let a run that panicked be retried with the closure gone.
+        live: AtomicU8,
+        ghost: PhantomData<(T, F)>,
+        #[cfg(feature = "metrics")]
+        metrics: InitMetrics,
+        #[cfg(feature = "diagnostics")]
+        defined_at: &'static std::panic::Location<'static>,
+        /// Where the lazy came among all lazies forced so far, from 1; 0
+        /// until it is forced.
+        #[cfg(feature = "diagnostics")]
+        forced_order: AtomicUsize,
+    }
+
+    // SAFETY:
+    // * forcing shares the value, hence `T: Sync`
+    // * the value may be created on one thread and dropped on another, hence
+    //   `T: Send`
+    // * the closure runs on whichever thread forces the lazy first, hence
+    //   `F: Send`; the backend lets only that thread touch it, so it needn't
+    //   be `Sync`
+    // * every thread forcing the lazy uses the backend, hence `B: Sync`
+    unsafe impl<T: Send + Sync, F: Send, B: Sync> Sync for Lazy<T, F, B> {}
+
+    impl<T, F> Lazy<T, F> {
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        pub const fn new(init: F) -> Self {
+            Lazy::with_backend(init, DefaultBackend::new())
+        }
+    }
+
+    impl<T, F, B: OnceBackend> Lazy<T, F, B> {
+        /// Like `new`, with another backend or policy:
+        ///
+        /// ```
+        /// use once_cell::sync::{Lazy, PolicyBackend, Poison, Spin};
+        ///
+        /// static PID: Lazy<u32, fn() -> u32, PolicyBackend<Spin, Poison>> =
+        ///     Lazy::with_backend(std::process::id, PolicyBackend::new());
+        ///
+        /// assert_eq!(*PID, std::process::id());
+        /// ```
+        ///
+        /// The closure is consumed by its run, so a lazy is poisoned by a
+        /// panicking initializer even if the backend would retry.
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        pub const fn with_backend(init: F, backend: B) -> Self {
+            Self {
+                backend,
+                data: UnsafeCell::new(LazyData {
+                    init: ManuallyDrop::new(init),
+                }),
+                live: AtomicU8::new(UNINIT),
+                ghost: PhantomData,
+                #[cfg(feature = "metrics")]
+                metrics: InitMetrics::new(),
+                #[cfg(feature = "diagnostics")]
+                defined_at: std::panic::Location::caller(),
+                #[cfg(feature = "diagnostics")]
+                forced_order: AtomicUsize::new(0),
+            }
+        }
+
+        #[inline]
+        fn record_slow_path(&self, _ran: bool) {
+            #[cfg(feature = "metrics")]
+            if !_ran {
+                self.metrics.contended.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        /// Moves the closure out of `data`; if the caller unwinds before
+        /// storing a value, the lazy is poisoned.
+        ///
+        /// # Safety
+        ///
+        /// Only from within a run of the backend.
+        unsafe fn take_init(&self) -> (F, PoisonOnDrop<'_>) {
+            // A backend that retries after a panic runs the next closure,
+            // but the lazy's is gone by then.
+            if self.live.load(Ordering::Relaxed) == POISONED {
+                panic!("OnceCell initializer panicked, the cell is poisoned");
+            }
+            let poison = PoisonOnDrop(&self.live);
+            // SAFETY:
+            // * the backend runs at most one closure at a time, and `live`
+            //   says the closure is still there; until the run completes, the
+            //   only other access to it is `clone`, which claims the backend
+            //   first
+            // * the guard marks the lazy poisoned if the caller unwinds, so
+            //   the closure is neither read nor dropped again
+            let init = unsafe { ManuallyDrop::take(&mut (*self.data.get()).init) };
+            (init, poison)
+        }
+
+        /// # Safety
+        ///
+        /// Only from within a run of the backend, after `take_init`.
+        unsafe fn store(&self, value: T, poison: PoisonOnDrop<'_>) {
+            std::mem::forget(poison);
+            // SAFETY:
+            // * readers only look at the value once the backend completed
+            unsafe { (*self.data.get()).value = ManuallyDrop::new(value) };
+            self.live.store(COMPLETE, Ordering::Relaxed);
+            #[cfg(feature = "diagnostics")]
+            {
+                static FORCED: AtomicUsize = AtomicUsize::new(0);
+                let order = FORCED.fetch_add(1, Ordering::Relaxed) + 1;
+                self.forced_order.store(order, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Marks a lazy poisoned when dropped, which covers unwinding.
+    struct PoisonOnDrop<'a>(&'a AtomicU8);
+
+    impl Drop for PoisonOnDrop<'_> {
+        fn drop(&mut self) {
+            self.0.store(POISONED, Ordering::Release);
+        }
+    }
+
+    impl<T, F: FnOnce() -> T, B: OnceBackend> Lazy<T, F, B> {
+        /// An associated function rather than a method, so that it can't shadow
+        /// a `force` method on `T`.
+        ///
+        /// Once the lazy is forced, this is a single `Acquire` load, like
+        /// [`OnceCell::get`]; the rest is kept out of line.
+        #[inline]
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        pub fn force(this: &Self) -> &T {
+            match Lazy::get(this) {
+                Some(value) => value,
+                None => Self::force_slow(this),
+            }
+        }
+
+        /// Like `force`, but returns an error instead of panicking if the
+        /// initializer panicked, on another thread or before. A panic of the
+        /// initializer run by this call still propagates.
+        ///
+        /// With a backend that doesn't override
+        /// [`OnceBackend::try_call_once`], waiting for an initializer that
+        /// panics still panics.
+        #[inline]
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        pub fn try_deref(this: &Self) -> Result<&T, LazyError> {
+            match Lazy::get(this) {
+                Some(value) => Ok(value),
+                None => Self::try_force_slow(this),
+            }
+        }
+
+        #[cold]
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        fn force_slow(this: &Self) -> &T {
+            match Self::try_force_slow(this) {
+                Ok(value) => value,
+                Err(_) => panic!("OnceCell initializer panicked, the cell is poisoned"),
+            }
+        }
+
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        fn try_force_slow(this: &Self) -> Result<&T, LazyError> {
+            #[cfg(feature = "diagnostics")]
+            {
+                let check = *ASYNC_CHECK.lock().unwrap_or_else(PoisonError::into_inner);
+                if (check.on_async_worker)() {
+                    (check.report)(&AsyncForce {
+                        defined_at: this.defined_at,
+                        forced_at: std::panic::Location::caller(),
+                        thread: std::thread::current(),
+                    });
+                }
+            }
+            if Lazy::is_poisoned(this) {
+                return Err(LazyError::Poisoned);
+            }
+            let mut ran = false;
+            let run = || {
+                ran = true;
+                #[cfg(feature = "metrics")]
+                let start = Instant::now();
+                // SAFETY:
+                // * called by the backend's run
+                let (f, poison) = unsafe { this.take_init() };
+                let value = observed(this, f);
+                #[cfg(feature = "metrics")]
+                this.metrics.record_init(start.elapsed());
+                // SAFETY:
+                // * still in the run, after `take_init`
+                unsafe { this.store(value, poison) };
+            };
+            let completed = this.backend.try_call_once(run);
+            this.record_slow_path(ran);
+            if completed {
+                Ok(Lazy::get(this).unwrap())
+            } else {
+                Err(LazyError::Poisoned)
+            }
+        }
+
+        /// Like `force`, but takes `&mut` and hands out `&mut T`.
+        pub fn force_mut(this: &mut Self) -> &mut T {
+            Self::force(this);
+            Self::get_mut(this).unwrap()
+        }
+
+        /// A lazy computed from this one's value. Whichever thread forces it
+        /// forces this one first, waiting if another thread already is.
+        ///
+        /// The returned closure type can't be named, so for statics spell the
+        /// dependency out instead: `Lazy::new(|| f(&*OTHER))`.
+        pub fn and_then<'a, U, G>(this: &'a Self, g: G) -> Lazy<U, impl FnOnce() -> U + 'a>
+        where
+            G: FnOnce(&'a T) -> U + 'a,
+        {
+            Lazy::new(move || g(Lazy::force(this)))
+        }
+
+        /// A lazy pairing the values of two others, forcing both on demand.
+        pub fn zip<'a, U, G, C: OnceBackend>(
+            this: &'a Self,
+            other: &'a Lazy<U, G, C>,
+        ) -> Lazy<(&'a T, &'a U), impl FnOnce() -> (&'a T, &'a U) + 'a>
+        where
+            G: FnOnce() -> U,
+        {
+            Lazy::new(move || (Lazy::force(this), Lazy::force(other)))
+        }
+    }
+
+    /// Inspecting a lazy needs no bounds on `F`. Like `force`, these are
+    /// associated functions so that they can't shadow methods of `T`.
+    impl<T, F, B: OnceBackend> Lazy<T, F, B> {
+        /// Returns `None` if the lazy hasn't been forced yet.
+        #[inline]
+        pub fn get(this: &Self) -> Option<&T> {
+            if this.backend.is_completed() {
+                // SAFETY:
+                // * written before the backend completed, never written again
+                Some(unsafe { &(*this.data.get()).value })
+            } else {
+                None
+            }
+        }
+
+        /// No synchronization needed: `&mut` rules out a concurrent force.
+        pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+            if this.backend.is_completed() {
+                // SAFETY:
+                // * a completed run stored the value
+                Some(unsafe { &mut this.data.get_mut().value })
+            } else {
+                None
+            }
+        }
+
+        /// Installs `value` as if the lazy had computed it, e.g. to inject a
+        /// test double, and drops the closure unrun. Fails if the lazy was
+        /// already forced or poisoned; waits if it is being forced.
+        pub fn preinit(this: &Self, value: T) -> Result<(), T> {
+            if Lazy::is_poisoned(this) {
+                return Err(value);
+            }
+            let mut value = Some(value);
+            let mut f = None;
+            this.backend.try_call_once(|| {
+                let value = value.take().unwrap();
+                // SAFETY:
+                // * called by the backend's run
+                let (init, poison) = unsafe { this.take_init() };
+                f = Some(init);
+                // SAFETY:
+                // * still in the run, after `take_init`
+                unsafe { this.store(value, poison) };
+            });
+            // Outside the run, so that a panicking drop doesn't poison the
+            // lazy holding `value`.
+            drop(f);
+            match value {
+                None => Ok(()),
+                Some(value) => Err(value),
+            }
+        }
+
+        /// Whether the initializer panicked, on this thread or another, so
+        /// that forcing the lazy again would panic too.
+        pub fn is_poisoned(this: &Self) -> bool {
+            this.live.load(Ordering::Acquire) == POISONED
+        }
+
+        /// Best-effort snapshot, for monitoring only, like
+        /// [`OnceCell::state`].
+        pub fn state(this: &Self) -> State {
+            if Lazy::is_poisoned(this) {
+                State::Poisoned
+            } else {
+                this.backend.state()
+            }
+        }
+
+        /// Where the lazy was created.
+        #[cfg(feature = "diagnostics")]
+        pub fn defined_at(this: &Self) -> &'static std::panic::Location<'static> {
+            this.defined_at
+        }
+
+        #[cfg(feature = "diagnostics")]
+        pub(crate) fn forced_order(this: &Self) -> Option<usize> {
+            match this.forced_order.load(Ordering::Relaxed) {
+                0 => None,
+                order => Some(order),
+            }
+        }
+    }
+
+    drop_may_dangle! {
+        impl<T, F, B> Drop for Lazy<T, F, B> {
+            fn drop(&mut self) {
+                let data = self.data.get_mut();
+                // SAFETY:
+                // * nobody can be forcing or cloning the lazy, so `live` is
+                //   settled and says which field, if any, is live
+                match *self.live.get_mut() {
+                    UNINIT => unsafe { ManuallyDrop::drop(&mut data.init) },
+                    COMPLETE => unsafe { ManuallyDrop::drop(&mut data.value) },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    impl<T, F> Lazy<T, F>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        /// Starts forcing the lazy on a background thread right away, for
+        /// values that are slow to compute but not needed immediately.
+        ///
+        /// `get` returns `None` until the value is ready, and `force` waits
+        /// for it. If `f` panics, the lazy is poisoned.
+        pub fn spawn(f: F) -> std::sync::Arc<Self> {
+            let lazy = std::sync::Arc::new(Lazy::new(f));
+            let background = lazy.clone();
+            std::thread::spawn(move || {
+                Lazy::force(&background);
+            });
+            lazy
+        }
+    }
+
+    impl<T, F: FnOnce() -> T, B: OnceBackend> Deref for Lazy<T, F, B> {
+        type Target = T;
+
+        #[inline]
+        #[cfg_attr(feature = "diagnostics", track_caller)]
+        fn deref(&self) -> &T {
+            Lazy::force(self)
+        }
+    }
+
+    impl<T: Default> Default for Lazy<T> {
+        fn default() -> Self {
+            Lazy::new(T::default)
+        }
+    }
+
+    impl<T, F, B> Lazy<T, F, B> {
+        /// See [`OnceCell::metrics`].
+        #[cfg(feature = "metrics")]
+        pub fn metrics(this: &Self) -> Metrics {
+            this.metrics.snapshot()
+        }
+    }
+
+    /// Clones the value if the lazy was forced, and the closure otherwise.
+    ///
+    /// If another thread is forcing the lazy at that moment, waits for it and
+    /// clones the value. `F::clone` must not force the lazy: that deadlocks,
+    /// like a reentrant `force`.
+    impl<T, F, W, P> Clone for Lazy<T, F, PolicyBackend<W, P>>
+    where
+        T: Clone,
+        F: FnOnce() -> T + Clone,
+        W: WaitPolicy,
+        P: PanicPolicy,
+    {
+        fn clone(&self) -> Self {
+            #[cfg_attr(not(feature = "diagnostics"), allow(unused_mut))]
+            let mut clone = self.clone_here();
+            // A clone reports where the original was defined.
+            #[cfg(feature = "diagnostics")]
+            {
+                clone.defined_at = self.defined_at;
+            }
+            clone
+        }
+    }
+
+    impl<T, F, W, P> Lazy<T, F, PolicyBackend<W, P>>
+    where
+        T: Clone,
+        F: FnOnce() -> T + Clone,
+        W: WaitPolicy,
+        P: PanicPolicy,
+    {
+        fn forced(value: T) -> Self {
+            Self {
+                backend: PolicyBackend::with_state(COMPLETE),
+                data: UnsafeCell::new(LazyData {
+                    value: ManuallyDrop::new(value),
+                }),
+                live: AtomicU8::new(COMPLETE),
+                ghost: PhantomData,
+                #[cfg(feature = "metrics")]
+                metrics: InitMetrics::new(),
+                #[cfg(feature = "diagnostics")]
+                defined_at: std::panic::Location::caller(),
+                #[cfg(feature = "diagnostics")]
+                forced_order: AtomicUsize::new(0),
+            }
+        }
+
+        fn clone_here(&self) -> Self {
+            loop {
+                if let Some(value) = Lazy::get(self) {
+                    return Self::forced(value.clone());
+                }
+                match self.backend.claim() {
+                    Ok(_release) if Lazy::is_poisoned(self) => {
+                        panic!("OnceCell initializer panicked, the cell is poisoned")
+                    }
+                    Ok(_release) => {
+                        // SAFETY:
+                        // * `live` says the closure is there, and while the
+                        //   backend is claimed no thread can start forcing
+                        //   and move it out
+                        let init = unsafe { (*(*self.data.get()).init).clone() };
+                        return Lazy::with_backend(init, PolicyBackend::new());
+                    }
+                    Err(POISONED) => panic!("OnceCell initializer panicked, the cell is poisoned"),
+                    Err(_) => {
+                        self.backend.wait(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Several cells published together: readers see either all the values
+    /// the group's initializer set, or none.
+    ///
+    /// ```
+    /// use once_cell::sync::{GroupCell, InitGroup};
+    ///
+    /// static GROUP: InitGroup = InitGroup::new();
+    /// static SCHEMA: GroupCell<String> = GroupCell::new(&GROUP);
+    /// static VERSION: GroupCell<u32> = GroupCell::new(&GROUP);
+    ///
+    /// GROUP.get_or_init(|init| {
+    ///     SCHEMA.set(init, String::from("v3 schema"));
+    ///     VERSION.set(init, 3);
+    /// });
+    /// assert_eq!(VERSION.get(), Some(&3));
+    /// ```
+    ///
+    /// All members share the group's state, so there is one flag to check,
+    /// and one initializer run for the whole group. A member the initializer
+    /// didn't set stays empty.
+    pub struct InitGroup {
+        backend: DefaultBackend,
+    }
+
+    /// Handed to the group's initializer, to set the members with.
+    pub struct GroupInit<'a> {
+        group: &'a InitGroup,
+        /// Keeps the initializer from setting members from other threads.
+        not_sync: PhantomData<std::cell::Cell<()>>,
+    }
+
+    impl InitGroup {
+        pub const fn new() -> Self {
+            Self {
+                backend: DefaultBackend::new(),
+            }
+        }
+
+        pub fn is_initialized(&self) -> bool {
+            self.backend.is_completed()
+        }
+
+        /// Runs `f` unless the group was already initialized, and publishes
+        /// every member `f` set once it returns. Like `OnceCell::get_or_init`,
+        /// concurrent callers wait, and a panic poisons the group.
+        pub fn get_or_init<F: FnOnce(&GroupInit<'_>)>(&self, f: F) {
+            if self.is_initialized() {
+                return;
+            }
+            self.backend.call_once(|| {
+                f(&GroupInit {
+                    group: self,
+                    not_sync: PhantomData,
+                })
+            });
+        }
+    }
+
+    impl Default for InitGroup {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// A member of an [`InitGroup`].
+    pub struct GroupCell<'a, T> {
+        group: &'a InitGroup,
+        value: UnsafeCell<Option<T>>,
+    }
+
+    // SAFETY:
+    // * same reasoning as for `OnceCell`
+    unsafe impl<T: Send + Sync> Sync for GroupCell<'_, T> {}
+
+    impl<'a, T> GroupCell<'a, T> {
+        pub const fn new(group: &'a InitGroup) -> Self {
+            Self {
+                group,
+                value: UnsafeCell::new(None),
+            }
+        }
+
+        /// `None` until the group is initialized, and for good if the group's
+        /// initializer didn't set this member.
+        pub fn get(&self) -> Option<&T> {
+            if self.group.is_initialized() {
+                // SAFETY:
+                // * only written during the group's run, which finished
+                unsafe { &*self.value.get() }.as_ref()
+            } else {
+                None
+            }
+        }
+
+        /// Sets the member from within the group's initializer; setting it
+        /// again replaces the value.
+        ///
+        /// Panics if `init` belongs to another group.
+        pub fn set(&self, init: &GroupInit<'_>, value: T) {
+            assert!(
+                std::ptr::eq(init.group, self.group),
+                "GroupCell set from another group's initializer"
+            );
+            // SAFETY:
+            // * a `GroupInit` only exists during the group's run, and can't be
+            //   shared with other threads; `get` hands out nothing until the
+            //   run finished
+            unsafe { *self.value.get() = Some(value) };
+        }
+    }
+
+    /// Memoizes a computation per key: each key is initialized at most once, and
+    /// the references handed out stay valid for as long as the `Memo` lives.
+    ///
+    /// The map is only locked to find a key's cell; initializers run outside of
+    /// it, so different keys can be initialized concurrently, and an initializer
+    /// may look up other keys.
+    pub struct Memo<K, V> {
+        map: Mutex<HashMap<K, Box<OnceCell<V>>>>,
+    }
+
+    // SAFETY:
+    // * the map is behind a mutex, and values are shared by reference between
+    //   the threads using the `Memo`
+    unsafe impl<K: Send, V: Send + Sync> Sync for Memo<K, V> {}
+
+    impl<K: Hash + Eq, V> Memo<K, V> {
+        pub fn new() -> Self {
+            Self {
+                map: Mutex::new(HashMap::new()),
+            }
+        }
+
+        pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            let map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
+            let cell: *const OnceCell<V> = &**map.get(key)?;
+            drop(map);
+            // SAFETY:
+            // * cells are boxed and never removed, so they outlive `&self`
+            unsafe { &*cell }.get()
+        }
+
+        pub fn get_or_init<F: FnOnce() -> V>(&self, key: K, f: F) -> &V {
+            let mut map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
+            let cell: *const OnceCell<V> = &**map.entry(key).or_default();
+            drop(map);
+            // SAFETY:
+            // * same as in `get`
+            unsafe { &*cell }.get_or_init(f)
+        }
+    }
+
+    impl<K: Hash + Eq, V> Default for Memo<K, V> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// `N` once slots indexed by position, e.g. one per CPU or per shard.
+    ///
+    /// Readers only look at a single word of "ready" bits, so `N` is limited to
+    /// the number of bits in a `usize`. Threads initializing different slots
+    /// don't wait for each other.
+    ///
+    /// Unlike [`OnceCell`], a slot isn't poisoned when its initializer panics:
+    /// it stays empty, and the next caller, or a thread that was waiting on
+    /// it, runs its own initializer.
+    pub struct OnceArray<T, const N: usize> {
+        ready: AtomicUsize,
+        running: Mutex<usize>,
+        cvar: Condvar,
+        slots: [UnsafeCell<MaybeUninit<T>>; N],
+    }
+
+    // SAFETY:
+    // * a slot is written only by the thread holding its running bit, and read
+    //   only after an `Acquire` load saw its ready bit, which is set with
+    //   `Release` once the write is done
+    // * a ready slot is never written again, so shared `&T`s are fine given
+    //   `T: Sync`
+    // * a slot may be written on one thread and dropped on another, hence
+    //   `T: Send`
+    unsafe impl<T: Send + Sync, const N: usize> Sync for OnceArray<T, N> {}
+
+    impl<T, const N: usize> OnceArray<T, N> {
+        const FITS: () = assert!(N <= usize::BITS as usize, "OnceArray is too long");
+
+        pub const fn new() -> Self {
+            let () = Self::FITS;
+            Self {
+                ready: AtomicUsize::new(0),
+                running: Mutex::new(0),
+                cvar: Condvar::new(),
+                slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            }
+        }
+
+        /// Returns `None` if slot `i` is empty or out of bounds.
+        pub fn get(&self, i: usize) -> Option<&T> {
+            if i < N && self.ready.load(Ordering::Acquire) & (1 << i) != 0 {
+                // SAFETY:
+                // * the ready bit is set with `Release` after the slot was
+                //   written, and the slot is never written again
+                Some(unsafe { (*self.slots[i].get()).assume_init_ref() })
+            } else {
+                None
+            }
+        }
+
+        /// Panics if `i` is out of bounds. Initializing slot `i` again from
+        /// `f` deadlocks, as with `OnceCell`; other slots are fine.
+        pub fn get_or_init<F: FnOnce() -> T>(&self, i: usize, f: F) -> &T {
+            assert!(
+                i < N,
+                "index {} out of bounds for OnceArray of length {}",
+                i,
+                N
+            );
+            if let Some(value) = self.get(i) {
+                return value;
+            }
+            let bit = 1 << i;
+            let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if let Some(value) = self.get(i) {
+                    return value;
+                }
+                if *running & bit == 0 {
+                    break;
+                }
+                running = self
+                    .cvar
+                    .wait(running)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            *running |= bit;
+            drop(running);
+
+            // Lets the next waiter have a go if `f` panics.
+            let _done = SlotDone { array: self, bit };
+            let value = f();
+            // SAFETY:
+            // * our running bit gives us exclusive access to the empty slot
+            unsafe { (*self.slots[i].get()).write(value) };
+            self.ready.fetch_or(bit, Ordering::Release);
+            self.get(i).unwrap()
+        }
+    }
+
+    impl<T, const N: usize> Default for OnceArray<T, N> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T, const N: usize> Drop for OnceArray<T, N> {
+        fn drop(&mut self) {
+            let ready = *self.ready.get_mut();
+            for (i, slot) in self.slots.iter_mut().enumerate() {
+                if ready & (1 << i) != 0 {
+                    // SAFETY:
+                    // * the slot was written, and we have exclusive access
+                    unsafe { slot.get_mut().assume_init_drop() }
+                }
+            }
+        }
+    }
+
+    struct SlotDone<'a, T, const N: usize> {
+        array: &'a OnceArray<T, N>,
+        bit: usize,
+    }
+
+    impl<T, const N: usize> Drop for SlotDone<'_, T, N> {
+        fn drop(&mut self) {
+            let mut running = self
+                .array
+                .running
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            *running &= !self.bit;
+            self.array.cvar.notify_all();
+        }
+    }
+
+    /// A lazy whose value lives in an `Arc`, so that callers can take owned
+    /// handles to it, e.g. to move into a spawned task, even when the lazy
+    /// isn't a static.
+    pub struct LazyArc<T, F = fn() -> T> {
+        cell: OnceCell<std::sync::Arc<T>>,
+        init: Mutex<Option<F>>,
+    }
+
+    impl<T, F> LazyArc<T, F> {
+        pub const fn new(f: F) -> Self {
+            Self {
+                cell: OnceCell::new(),
+                init: Mutex::new(Some(f)),
+            }
+        }
+
+        pub fn get(this: &Self) -> Option<std::sync::Arc<T>> {
+            this.cell.get().cloned()
+        }
+    }
+
+    impl<T, F: FnOnce() -> T> LazyArc<T, F> {
+        /// Forces the lazy and returns a new handle to its value.
+        pub fn arc(this: &Self) -> std::sync::Arc<T> {
+            Self::force_arc(this).clone()
+        }
+
+        pub fn force(this: &Self) -> &T {
+            Self::force_arc(this)
+        }
+
+        fn force_arc(this: &Self) -> &std::sync::Arc<T> {
+            this.cell.get_or_init(|| {
+                let f = this
+                    .init
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .take();
+                // The cell runs this at most once, and poisons itself if it
+                // panics.
+                std::sync::Arc::new(f.unwrap()())
+            })
+        }
+    }
+
+    impl<T, F: FnOnce() -> T> Deref for LazyArc<T, F> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            LazyArc::force(self)
+        }
+    }
+
+    impl<T: Default> Default for LazyArc<T> {
+        fn default() -> Self {
+            LazyArc::new(T::default)
+        }
+    }
+
+    /// Types with a spare all-zero bit pattern, so that a [`OnceWord`] can
+    /// use zero for "empty".
+    ///
+    /// # Safety
+    ///
+    /// `from_word` must turn every word returned by `into_word` back into the
+    /// same value.
+    pub unsafe trait Niche: Copy {
+        fn into_word(self) -> NonZeroUsize;
+
+        /// # Safety
+        ///
+        /// `word` came from `into_word`.
+        unsafe fn from_word(word: NonZeroUsize) -> Self;
+    }
+
+    // SAFETY:
+    // * a `NonZeroUsize` is never 0, so zero stays free for "empty", and the
+    //   word is the value itself
+    unsafe impl Niche for NonZeroUsize {
+        fn into_word(self) -> NonZeroUsize {
+            self
+        }
+
+        unsafe fn from_word(word: NonZeroUsize) -> Self {
+            word
+        }
+    }
+
+    // SAFETY:
+    // * a `NonZeroU32` is never 0, and widening it to `usize` is lossless, so
+    //   narrowing the word back gives the same value
+    #[cfg(any(target_pointer_width = "32", target_pointer_width = "64"))]
+    unsafe impl Niche for std::num::NonZeroU32 {
+        fn into_word(self) -> NonZeroUsize {
+            // SAFETY:
+            // * `usize` is at least 32 bits wide here, so this stays non-zero
+            unsafe { NonZeroUsize::new_unchecked(self.get() as usize) }
+        }
+
+        unsafe fn from_word(word: NonZeroUsize) -> Self {
+            // SAFETY:
+            // * `word` came from a `NonZeroU32`
+            unsafe { Self::new_unchecked(word.get() as u32) }
+        }
+    }
+
+    // SAFETY:
+    // * references are never null, and the word is the reference's exposed
+    //   address, which `from_word` turns back into the same reference
+    unsafe impl<T: 'static> Niche for &'static T {
+        fn into_word(self) -> NonZeroUsize {
+            // SAFETY:
+            // * references are never null
+            unsafe { NonZeroUsize::new_unchecked((self as *const T).expose_provenance()) }
+        }
+
+        unsafe fn from_word(word: NonZeroUsize) -> Self {
+            // SAFETY:
+            // * `word` is the exposed address of a `&'static T`
+            unsafe { &*std::ptr::with_exposed_provenance(word.get()) }
+        }
+    }
+
+    /// Addresses of the `OnceWord`s whose initializer is running. Waiting is
+    /// rare enough for every cell to share the list.
+    static INITIALIZING_WORDS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    static WORD_DONE: Condvar = Condvar::new();
+
+    /// A blocking once cell that is a single word, for `NonZeroUsize`,
+    /// `&'static T` and other [`Niche`] types: zero stands for "empty", and
+    /// threads waiting for an initializer park on a global list.
+    ///
+    /// Unlike `OnceCell`, a panicking initializer leaves the cell empty
+    /// rather than poisoned, and `set` doesn't wait for a running
+    /// initializer: the first value stored wins. For `NonNull<T>`, which
+    /// isn't `Sync`, see `race::OnceNonNull`.
+    pub struct OnceWord<T> {
+        word: AtomicUsize,
+        ghost: PhantomData<*const T>,
+    }
+
+    // SAFETY:
+    // * values are copied out on any thread, hence `T: Send + Sync`
+    unsafe impl<T: Send + Sync> Sync for OnceWord<T> {}
+    unsafe impl<T: Send> Send for OnceWord<T> {}
+
+    impl<T: Niche> OnceWord<T> {
+        pub const fn new() -> Self {
+            Self {
+                word: AtomicUsize::new(0),
+                ghost: PhantomData,
+            }
+        }
+
+        #[inline]
+        pub fn get(&self) -> Option<T> {
+            let word = NonZeroUsize::new(self.word.load(Ordering::Acquire))?;
+            // SAFETY:
+            // * only words from `into_word` are ever stored
+            Some(unsafe { T::from_word(word) })
+        }
+
+        pub fn set(&self, value: T) -> Result<(), T> {
+            self.store(value).map(drop).map_err(|_| value)
+        }
+
+        /// Stores `value` unless the cell is full, returning the stored
+        /// value either way.
+        fn store(&self, value: T) -> Result<T, T> {
+            match self.word.compare_exchange(
+                0,
+                value.into_word().get(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => Ok(value),
+                // SAFETY:
+                // * as in `get`
+                Err(word) => Err(unsafe { T::from_word(NonZeroUsize::new_unchecked(word)) }),
+            }
+        }
+
+        /// Initializing the cell again from `f` deadlocks.
+        #[inline]
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> T {
+            match self.get() {
+                Some(value) => value,
+                None => self.init_slow(f),
+            }
+        }
+
+        #[cold]
+        fn init_slow<F: FnOnce() -> T>(&self, f: F) -> T {
+            struct Done(usize);
+
+            impl Drop for Done {
+                fn drop(&mut self) {
+                    let mut words = INITIALIZING_WORDS
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    if let Some(i) = words.iter().position(|&word| word == self.0) {
+                        words.swap_remove(i);
+                    }
+                    drop(words);
+                    WORD_DONE.notify_all();
+                }
+            }
+
+            let addr = self as *const Self as usize;
+            let mut words = INITIALIZING_WORDS
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if let Some(value) = self.get() {
+                    return value;
+                }
+                if !words.contains(&addr) {
+                    break;
+                }
+                words = WORD_DONE
+                    .wait(words)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            words.push(addr);
+            drop(words);
+
+            let _done = Done(addr);
+            match self.store(f()) {
+                Ok(value) | Err(value) => value,
+            }
+        }
+    }
+
+    impl<T: Niche> Default for OnceWord<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    try_once_cell! {
+        /// A thread-safe cell for initializers whose failure is permanent: the
+        /// first outcome, `Ok` or `Err`, is cached and handed to every thread,
+        /// so a failed initializer isn't rerun on every access.
+        ///
+        /// Threads that find the cell being initialized wait for the outcome.
+        /// An initializer that panics rather than returning `Err` poisons the
+        /// cell, as with [`OnceCell`].
+    }
+
+    /// A once cell whose value lives in caller-provided storage, e.g. a
+    /// buffer reserved in a firmware image, rather than inside the cell.
+    ///
+    /// ```
+    /// use once_cell::sync::StaticOnceCell;
+    /// use std::mem::MaybeUninit;
+    ///
+    /// static mut BUF: MaybeUninit<[u8; 4096]> = MaybeUninit::uninit();
+    /// // SAFETY: `BUF` isn't used anywhere else.
+    /// static FRAME: StaticOnceCell<[u8; 4096]> =
+    ///     unsafe { StaticOnceCell::from_raw(std::ptr::addr_of_mut!(BUF)) };
+    ///
+    /// let frame: &'static [u8; 4096] = FRAME.get_or_init(|| [0xff; 4096]);
+    /// assert_eq!(frame[0], 0xff);
+    /// ```
+    ///
+    /// The value is never dropped.
+    pub struct StaticOnceCell<T: 'static> {
+        backend: DefaultBackend,
+        slot: *mut T,
+    }
+
+    // SAFETY:
+    // * same reasoning as for `OnceCell`
+    unsafe impl<T: Send + Sync> Sync for StaticOnceCell<T> {}
+    unsafe impl<T: Send> Send for StaticOnceCell<T> {}
+
+    impl<T> StaticOnceCell<T> {
+        pub fn new(buf: &'static mut MaybeUninit<T>) -> Self {
+            // SAFETY:
+            // * a `&'static mut` is valid and unaliased forever
+            unsafe { Self::from_raw(buf) }
+        }
+
+        /// A `const` constructor, for statics.
+        ///
+        /// # Safety
+        ///
+        /// `buf` must be valid for writes and reads for `'static`, and not be
+        /// accessed other than through this cell.
+        pub const unsafe fn from_raw(buf: *mut MaybeUninit<T>) -> Self {
+            Self {
+                backend: DefaultBackend::new(),
+                slot: buf.cast(),
+            }
+        }
+
+        pub fn get(&self) -> Option<&'static T> {
+            if self.backend.is_completed() {
+                // SAFETY:
+                // * written before the backend completed, never written again
+                Some(unsafe { &*self.slot })
+            } else {
+                None
+            }
+        }
+
+        pub fn set(&self, value: T) -> Result<(), T> {
+            let mut value = Some(value);
+            self.get_or_init(|| value.take().unwrap());
+            match value {
+                None => Ok(()),
+                Some(value) => Err(value),
+            }
+        }
+
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &'static T {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            // SAFETY:
+            // * the backend runs at most one closure, and readers only look at
+            //   the slot once it completed
+            self.backend.call_once(|| unsafe { self.slot.write(f()) });
+            self.get().unwrap()
+        }
+    }
+}
+
+/// Lock-free cells that never block: if several threads initialize one at the
+/// same time, they all run their initializer and the first to finish wins.
+///
+/// # Async-signal safety
+///
+/// `OnceNonZeroUsize`, `OnceNonNull` and `OnceFn` are async-signal-safe: their
+/// methods neither allocate nor lock, and each is a few atomic operations
+/// with no retry loop. They can be read and set
+/// from a Unix signal handler, even one that interrupted another access to
+/// the same cell, provided the initializer is async-signal-safe itself.
+/// `OnceBox` allocates, and `OnceSlice::get_or_init` may yield to a writer
+/// the handler interrupted, so they aren't.
+pub mod race {
+    use std::fmt;
+    use std::marker::PhantomData;
+    use std::num::NonZeroUsize;
+    use std::ptr::{self, NonNull};
+    use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+    /// A once cell holding a `Box<T>`, set with a single compare-and-swap.
+    pub struct OnceBox<T> {
+        inner: AtomicPtr<T>,
+        ghost: PhantomData<Option<Box<T>>>,
+    }
+
+    // SAFETY:
+    // * same reasoning as for `sync::OnceCell`
+    unsafe impl<T: Send + Sync> Sync for OnceBox<T> {}
+
+    impl<T> OnceBox<T> {
+        pub const fn new() -> Self {
+            Self {
+                inner: AtomicPtr::new(ptr::null_mut()),
+                ghost: PhantomData,
+            }
+        }
+
+        pub fn get(&self) -> Option<&T> {
+            let ptr = self.inner.load(Ordering::Acquire);
+            // SAFETY:
+            // * a non-null pointer came from `Box::into_raw`, was published with
+            //   `Release`, and stays valid until the cell is dropped
+            unsafe { ptr.as_ref() }
+        }
+
+        pub fn set(&self, value: Box<T>) -> Result<(), Box<T>> {
+            let ptr = Box::into_raw(value);
+            match self.inner.compare_exchange(
+                ptr::null_mut(),
+                ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => Ok(()),
+                // SAFETY:
+                // * we still own the box, nobody else saw the pointer
+                Err(_) => Err(unsafe { Box::from_raw(ptr) }),
+            }
+        }
+
+        /// If another thread gets there first, `f`'s box is dropped and the
+        /// other thread's value is returned.
+        pub fn get_or_init<F: FnOnce() -> Box<T>>(&self, f: F) -> &T {
+            enum Void {}
+            match self.get_or_try_init(|| Ok::<_, Void>(f())) {
+                Ok(value) => value,
+                Err(void) => match void {},
+            }
+        }
+
+        /// Like `get_or_init`, but leaves the cell empty if `f` fails.
+        pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+        where
+            F: FnOnce() -> Result<Box<T>, E>,
+        {
+            if let Some(value) = self.get() {
+                return Ok(value);
+            }
+            let ptr = Box::into_raw(f()?);
+            let ptr = match self.inner.compare_exchange(
+                ptr::null_mut(),
+                ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => ptr,
+                Err(winner) => {
+                    // SAFETY:
+                    // * we lost, so the box is still ours alone
+                    drop(unsafe { Box::from_raw(ptr) });
+                    winner
+                }
+            };
+            // SAFETY:
+            // * same as in `get`
+            Ok(unsafe { &*ptr })
+        }
+
+        /// Empties the cell, handing back ownership of the box.
+        pub fn take(&mut self) -> Option<Box<T>> {
+            let ptr = std::mem::replace(self.inner.get_mut(), ptr::null_mut());
+            if ptr.is_null() {
+                None
+            } else {
+                // SAFETY:
+                // * the pointer came from `Box::into_raw`, and we just took it out
+                Some(unsafe { Box::from_raw(ptr) })
+            }
+        }
+
+        pub fn into_inner(mut self) -> Option<Box<T>> {
+            self.take()
+        }
+
+        /// Gives up on ever dropping the value, for a `'static` reference to
+        /// it. An empty cell is simply dropped.
+        pub fn leak(self) -> Option<&'static T>
+        where
+            T: 'static,
+        {
+            self.into_inner().map(Box::leak).map(|value| &*value)
+        }
+    }
+
+    impl<T> Default for OnceBox<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    drop_may_dangle! {
+        impl<T> Drop for OnceBox<T> {
+            fn drop(&mut self) {
+                drop(self.take())
+            }
+        }
+    }
+
+    impl<T> From<Box<T>> for OnceBox<T> {
+        fn from(value: Box<T>) -> Self {
+            Self {
+                inner: AtomicPtr::new(Box::into_raw(value)),
+                ghost: PhantomData,
+            }
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for OnceBox<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("OnceBox").field(&self.get()).finish()
+        }
+    }
+
+    impl<T: PartialEq> PartialEq for OnceBox<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.get() == other.get()
+        }
+    }
+
+    impl<T: Eq> Eq for OnceBox<T> {}
+
+    /// A once cell holding a function pointer, for caching the implementation
+    /// picked by CPU feature detection and the like:
+    ///
+    /// ```
+    /// use once_cell::race::OnceFn;
+    ///
+    /// fn popcount_scalar(mut x: u64) -> u32 {
+    ///     let mut n = 0;
+    ///     while x != 0 {
+    ///         x &= x - 1;
+    ///         n += 1;
+    ///     }
+    ///     n
+    /// }
+    ///
+    /// static POPCOUNT: OnceFn<fn(u64) -> u32> = OnceFn::new();
+    ///
+    /// let popcount = POPCOUNT.get_or_select(|| popcount_scalar);
+    /// assert_eq!(popcount(0b1011), 3);
+    /// ```
+    ///
+    /// `new` takes the types listed under [`FnPtr`]. Signatures that borrow,
+    /// like `fn(&[u32]) -> u32`, are higher-ranked and can't all be covered
+    /// by impls; use the `unsafe` `new_unchecked` for those.
+    pub struct OnceFn<F> {
+        inner: AtomicPtr<()>,
+        ghost: PhantomData<F>,
+    }
+
+    mod sealed {
+        pub trait Sealed {}
+    }
+
+    /// The function pointer types [`OnceFn::new`] accepts: `fn`, `unsafe fn`,
+    /// `extern "C" fn` and `unsafe extern "C" fn` of up to six arguments,
+    /// without higher-ranked lifetimes.
+    pub trait FnPtr: Copy + sealed::Sealed {}
+
+    macro_rules! fn_ptr {
+        ($($arg:ident),*) => {
+            fn_ptr!(@impl fn($($arg),*) -> R; $($arg),*);
+            fn_ptr!(@impl unsafe fn($($arg),*) -> R; $($arg),*);
+            fn_ptr!(@impl extern "C" fn($($arg),*) -> R; $($arg),*);
+            fn_ptr!(@impl unsafe extern "C" fn($($arg),*) -> R; $($arg),*);
+        };
+        (@impl $ty:ty; $($arg:ident),*) => {
+            impl<R, $($arg),*> sealed::Sealed for $ty {}
+            impl<R, $($arg),*> FnPtr for $ty {}
+        };
+    }
+
+    fn_ptr!();
+    fn_ptr!(A);
+    fn_ptr!(A, B);
+    fn_ptr!(A, B, C);
+    fn_ptr!(A, B, C, D);
+    fn_ptr!(A, B, C, D, E);
+    fn_ptr!(A, B, C, D, E, G);
+
+    impl<F: FnPtr> OnceFn<F> {
+        pub const fn new() -> Self {
+            // SAFETY:
+            // * `F` is a function pointer, and those are never null
+            unsafe { Self::new_unchecked() }
+        }
+    }
+
+    impl<F: Copy> OnceFn<F> {
+        const FITS: () = assert!(
+            std::mem::size_of::<F>() == std::mem::size_of::<*mut ()>(),
+            "OnceFn only holds pointer-sized values"
+        );
+
+        /// # Safety
+        ///
+        /// `F` must be a function pointer type, or at least a pointer-sized
+        /// type without padding of which no valid value is all zeros: the
+        /// cell uses all zeros for "empty".
+        pub const unsafe fn new_unchecked() -> Self {
+            let () = Self::FITS;
+            Self {
+                inner: AtomicPtr::new(ptr::null_mut()),
+                ghost: PhantomData,
+            }
+        }
+
+        pub fn get(&self) -> Option<F> {
+            let raw = self.inner.load(Ordering::Acquire);
+            if raw.is_null() {
+                None
+            } else {
+                // SAFETY:
+                // * a non-null pointer was stored by `set`, from the bits of
+                //   an `F`
+                Some(unsafe { std::mem::transmute_copy::<*mut (), F>(&raw) })
+            }
+        }
+
+        pub fn set(&self, f: F) -> Result<(), F> {
+            // SAFETY:
+            // * `FITS` makes the sizes match, the constructor's contract rules
+            //   out padding, and `F: Copy` rules out drop glue
+            let raw = unsafe { std::mem::transmute_copy::<F, *mut ()>(&f) };
+            match self.inner.compare_exchange(
+                ptr::null_mut(),
+                raw,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(f),
+            }
+        }
+
+        /// Threads that find the cell empty may all run `select`; the first
+        /// pointer stored wins and is returned to everyone.
+        pub fn get_or_select<S: FnOnce() -> F>(&self, select: S) -> F {
+            if let Some(f) = self.get() {
+                return f;
+            }
+            let f = select();
+            match self.set(f) {
+                Ok(()) => f,
+                Err(_) => self.get().unwrap(),
+            }
+        }
+    }
+
+    impl<F: FnPtr> Default for OnceFn<F> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<F: FnPtr> From<F> for OnceFn<F> {
+        fn from(f: F) -> Self {
+            let cell = Self::new();
+            let _ = cell.set(f);
+            cell
+        }
+    }
+
+    impl<F: Copy + fmt::Debug> fmt::Debug for OnceFn<F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("OnceFn").field(&self.get()).finish()
+        }
+    }
+
+    impl<F: Copy + PartialEq> PartialEq for OnceFn<F> {
+        fn eq(&self, other: &Self) -> bool {
+            self.get() == other.get()
+        }
+    }
+
+    /// A once cell holding a `&'static [T]`, e.g. a table decompressed once
+    /// and leaked.
+    ///
+    /// The pointer and length can't be swapped in together, so a writer
+    /// first claims the cell, then stores the length and publishes the
+    /// pointer. A `get_or_init` that loses to a writer caught between the two
+    /// yields until the pointer shows up.
+    pub struct OnceSlice<T: 'static> {
+        ptr: AtomicPtr<T>,
+        len: AtomicUsize,
+        claimed: AtomicBool,
+        ghost: PhantomData<&'static [T]>,
+    }
+
+    impl<T> OnceSlice<T> {
+        pub const fn new() -> Self {
+            Self {
+                ptr: AtomicPtr::new(ptr::null_mut()),
+                len: AtomicUsize::new(0),
+                claimed: AtomicBool::new(false),
+                ghost: PhantomData,
+            }
+        }
+
+        pub fn get(&self) -> Option<&'static [T]> {
+            let ptr = self.ptr.load(Ordering::Acquire);
+            if ptr.is_null() {
+                return None;
+            }
+            // SAFETY:
+            // * a non-null pointer was published by `set`, with `Release`,
+            //   after storing the length of the same `&'static [T]`
+            Some(unsafe { std::slice::from_raw_parts(ptr, self.len.load(Ordering::Relaxed)) })
+        }
+
+        /// Fails if another slice was set first, even if `get` doesn't see it
+        /// yet.
+        pub fn set(&self, slice: &'static [T]) -> Result<(), &'static [T]> {
+            if self.claimed.swap(true, Ordering::Acquire) {
+                return Err(slice);
+            }
+            self.len.store(slice.len(), Ordering::Relaxed);
+            // Slices never have a null pointer, even empty ones.
+            self.ptr.store(slice.as_ptr() as *mut T, Ordering::Release);
+            Ok(())
+        }
+
+        /// If another thread gets there first, `f`'s slice is discarded and
+        /// the other thread's is returned.
+        pub fn get_or_init<F: FnOnce() -> &'static [T]>(&self, f: F) -> &'static [T] {
+            if let Some(slice) = self.get() {
+                return slice;
+            }
+            let slice = f();
+            if self.set(slice).is_ok() {
+                return slice;
+            }
+            loop {
+                if let Some(slice) = self.get() {
+                    return slice;
+                }
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    impl<T> Default for OnceSlice<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T> From<&'static [T]> for OnceSlice<T> {
+        fn from(slice: &'static [T]) -> Self {
+            let cell = Self::new();
+            let _ = cell.set(slice);
+            cell
+        }
+    }
+
+    /// Reads through `get`, so a slice that's still being published shows as
+    /// `None`, never as a pointer with the wrong length.
+    impl<T: fmt::Debug> fmt::Debug for OnceSlice<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("OnceSlice").field(&self.get()).finish()
+        }
+    }
+
+    impl<T: PartialEq> PartialEq for OnceSlice<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.get() == other.get()
+        }
+    }
+
+    impl<T: Eq> Eq for OnceSlice<T> {}
+
+    /// A once cell holding a `NonZeroUsize`, so that zero can stand for
+    /// "empty" and the cell is a single word. See the module docs for its
+    /// use from signal handlers.
+    #[derive(Default)]
+    pub struct OnceNonZeroUsize {
+        inner: AtomicUsize,
+    }
+
+    impl OnceNonZeroUsize {
+        pub const fn new() -> Self {
+            Self {
+                inner: AtomicUsize::new(0),
+            }
+        }
+
+        pub fn get(&self) -> Option<NonZeroUsize> {
+            NonZeroUsize::new(self.inner.load(Ordering::Acquire))
+        }
+
+        pub fn set(&self, value: NonZeroUsize) -> Result<(), NonZeroUsize> {
+            match self
+                .inner
+                .compare_exchange(0, value.get(), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => Ok(()),
+                Err(_) => Err(value),
+            }
+        }
+
+        /// If another thread (or an interrupting signal handler) gets there
+        /// first, `f`'s value is discarded and the other one is returned.
+        pub fn get_or_init<F: FnOnce() -> NonZeroUsize>(&self, f: F) -> NonZeroUsize {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            let value = f();
+            match self
+                .inner
+                .compare_exchange(0, value.get(), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => value,
+                // SAFETY:
+                // * only non-zero values are ever stored
+                Err(winner) => unsafe { NonZeroUsize::new_unchecked(winner) },
+            }
+        }
+    }
+
+    impl From<NonZeroUsize> for OnceNonZeroUsize {
+        fn from(value: NonZeroUsize) -> Self {
+            Self {
+                inner: AtomicUsize::new(value.get()),
+            }
+        }
+    }
+
+    impl fmt::Debug for OnceNonZeroUsize {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("OnceNonZeroUsize")
+                .field(&self.get())
+                .finish()
+        }
+    }
+
+    impl PartialEq for OnceNonZeroUsize {
+        fn eq(&self, other: &Self) -> bool {
+            self.get() == other.get()
+        }
+    }
+
+    impl Eq for OnceNonZeroUsize {}
+
+    /// A once cell holding a `NonNull<T>`, a single pointer-sized word. It
+    /// only stores the pointer; what it points to, and for how long, is up
+    /// to the caller. See the module docs for its use from signal handlers.
+    pub struct OnceNonNull<T> {
+        inner: AtomicPtr<T>,
+    }
+
+    impl<T> OnceNonNull<T> {
+        pub const fn new() -> Self {
+            Self {
+                inner: AtomicPtr::new(ptr::null_mut()),
+            }
+        }
+
+        pub fn get(&self) -> Option<NonNull<T>> {
+            NonNull::new(self.inner.load(Ordering::Acquire))
+        }
+
+        pub fn set(&self, value: NonNull<T>) -> Result<(), NonNull<T>> {
+            match self.inner.compare_exchange(
+                ptr::null_mut(),
+                value.as_ptr(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(value),
+            }
+        }
+
+        /// If another thread (or an interrupting signal handler) gets there
+        /// first, `f`'s pointer is discarded and the other one is returned.
+        pub fn get_or_init<F: FnOnce() -> NonNull<T>>(&self, f: F) -> NonNull<T> {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            let value = f();
+            match self.inner.compare_exchange(
+                ptr::null_mut(),
+                value.as_ptr(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => value,
+                // SAFETY:
+                // * only non-null pointers are ever stored
+                Err(winner) => unsafe { NonNull::new_unchecked(winner) },
+            }
+        }
+    }
+
+    impl<T> Default for OnceNonNull<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T> From<NonNull<T>> for OnceNonNull<T> {
+        fn from(value: NonNull<T>) -> Self {
+            Self {
+                inner: AtomicPtr::new(value.as_ptr()),
+            }
+        }
+    }
+
+    impl<T> fmt::Debug for OnceNonNull<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("OnceNonNull").field(&self.get()).finish()
+        }
+    }
+
+    impl<T> PartialEq for OnceNonNull<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.get() == other.get()
+        }
+    }
+
+    impl<T> Eq for OnceNonNull<T> {}
+}
+
+/// Lazies that can be recomputed, e.g. to reload configuration on `SIGHUP`.
+///
+/// Unlike the once types, these hand out `Arc`s: a reload swaps in a new
+/// value while readers holding the old one keep it alive.
+#[cfg(feature = "reload")]
+pub mod reload {
+    use std::marker::PhantomData;
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    /// A value computed by `init` on first access, and again by every
+    /// [`reload`](ReloadableLazy::reload) or first access after an
+    /// [`invalidate`](ReloadableLazy::invalidate).
+    ///
+    /// Reading the current value takes no lock: readers announce themselves
+    /// in a counter of the current generation while they clone the `Arc`.
+    /// A reload or invalidate starts a new generation and waits only for the
+    /// readers of the old one, which may have loaded the value it replaced,
+    /// so readers that keep coming don't hold it up.
+    pub struct ReloadableLazy<T, F = fn() -> T> {
+        /// From `Arc::into_raw`, or null; owns one strong count.
+        current: AtomicPtr<T>,
+        /// Bumped by every swap of `current`.
+        generation: AtomicUsize,
+        /// Readers between loading `current` and cloning what they loaded,
+        /// by the parity of the generation they joined.
+        readers: [AtomicUsize; 2],
+        /// Serializes swaps, so that at most two generations have readers.
+        swapping: Mutex<()>,
+        /// Held while `init` runs, so that concurrent first accesses compute
+        /// one value; readers of the current value don't wait for it.
+        computing: Mutex<()>,
+        epoch: AtomicU64,
+        init: F,
+        owns: PhantomData<Arc<T>>,
+    }
+
+    // SAFETY:
+    // * the value is shared with and may be dropped by other threads, as for
+    //   `Arc<T>`, hence `T: Send + Sync`
+    // * `init` goes wherever the lazy goes
+    unsafe impl<T: Send + Sync, F: Send> Send for ReloadableLazy<T, F> {}
+
+    // SAFETY:
+    // * as above for the value
+    // * `init` is called through `&self` from whichever thread computes,
+    //   hence `F: Sync`
+    unsafe impl<T: Send + Sync, F: Sync> Sync for ReloadableLazy<T, F> {}
+
+    impl<T, F> ReloadableLazy<T, F> {
+        pub const fn new(init: F) -> Self {
+            Self {
+                current: AtomicPtr::new(ptr::null_mut()),
+                generation: AtomicUsize::new(0),
+                readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+                swapping: Mutex::new(()),
+                computing: Mutex::new(()),
+                epoch: AtomicU64::new(0),
+                init,
+                owns: PhantomData,
+            }
+        }
+
+        fn current(&self) -> Option<Arc<T>> {
+            let readers = loop {
+                let generation = self.generation.load(Ordering::SeqCst);
+                let readers = &self.readers[generation % 2];
+                readers.fetch_add(1, Ordering::SeqCst);
+                // Still the same generation, so a swap ending it waits for us.
+                if self.generation.load(Ordering::SeqCst) == generation {
+                    break readers;
+                }
+                readers.fetch_sub(1, Ordering::Release);
+            };
+            let current = self.current.load(Ordering::SeqCst);
+            let value = if current.is_null() {
+                None
+            } else {
+                // SAFETY:
+                // * `replace` doesn't drop the strong count owned by `current`
+                //   until the readers of our generation left, so the pointer
+                //   is still live
+                unsafe {
+                    Arc::increment_strong_count(current);
+                    Some(Arc::from_raw(current))
+                }
+            };
+            readers.fetch_sub(1, Ordering::Release);
+            value
+        }
+
+        /// Swaps `value` in, and returns the old value once no reader can
+        /// still be cloning it.
+        fn replace(&self, value: Option<Arc<T>>) -> Option<Arc<T>> {
+            let _swapping = self.swapping.lock().unwrap_or_else(PoisonError::into_inner);
+            let new = value.map_or(ptr::null_mut(), |value| Arc::into_raw(value).cast_mut());
+            let old = self.current.swap(new, Ordering::SeqCst);
+            // A reader that loaded `old` joined the generation that ends here:
+            // one joining later confirms the new generation, after the swap,
+            // and the generation before was drained by the previous swap.
+            let generation = self.generation.fetch_add(1, Ordering::SeqCst);
+            while self.readers[generation % 2].load(Ordering::SeqCst) != 0 {
+                std::thread::yield_now();
+            }
+            // SAFETY:
+            // * `old` came from `Arc::into_raw`, and its strong count is ours
+            //   now that it's out of `current`
+            (!old.is_null()).then(|| unsafe { Arc::from_raw(old) })
+        }
+
+        /// Drops the current value; the next `get` computes a new one.
+        pub fn invalidate(&self) {
+            drop(self.replace(None));
+        }
+
+        /// How many values have been computed so far.
+        pub fn epoch(&self) -> u64 {
+            self.epoch.load(Ordering::Acquire)
+        }
+    }
+
+    impl<T, F: Fn() -> T> ReloadableLazy<T, F> {
+        pub fn get(&self) -> Arc<T> {
+            if let Some(value) = self.current() {
+                return value;
+            }
+            let _computing = self
+                .computing
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            match self.current() {
+                Some(value) => value,
+                None => self.compute(),
+            }
+        }
+
+        /// Computes a new value and swaps it in. Until it is ready, `get`
+        /// keeps returning the old one.
+        ///
+        /// If `init` panics, the old value stays.
+        pub fn reload(&self) -> Arc<T> {
+            let _computing = self
+                .computing
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            self.compute()
+        }
+
+        /// Must be called with `computing` held.
+        fn compute(&self) -> Arc<T> {
+            let value = Arc::new((self.init)());
+            let old = self.replace(Some(value.clone()));
+            self.epoch.fetch_add(1, Ordering::AcqRel);
+            drop(old);
+            value
+        }
+    }
+
+    impl<T, F> Drop for ReloadableLazy<T, F> {
+        fn drop(&mut self) {
+            let current = *self.current.get_mut();
+            if !current.is_null() {
+                // SAFETY:
+                // * `current` owns a strong count, and nobody can read it now
+                drop(unsafe { Arc::from_raw(current) });
+            }
+        }
+    }
+}
+
+/// Forces lazy statics in bulk, so that a server can pay for them during
+/// startup rather than on its first requests.
+///
+/// Lazies are registered with [`register`](prewarm::register), or declared with
+/// `prewarm_lazy!` (behind the `ctor` feature) to register themselves.
+pub mod prewarm {
+    use crate::sync::Lazy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Mutex, PoisonError};
+
+    trait Force: Sync {
+        fn force(&self);
+    }
+
+    impl<T: Send + Sync, F: FnOnce() -> T + Send> Force for Lazy<T, F> {
+        fn force(&self) {
+            Lazy::force(self);
+        }
+    }
+
+    static LAZIES: Mutex<Vec<&'static dyn Force>> = Mutex::new(Vec::new());
+
+    /// Adds `lazy` to the ones forced by [`force_all`]. Registering a lazy
+    /// twice is harmless.
+    pub fn register<T, F>(lazy: &'static Lazy<T, F>)
+    where
+        T: Send + Sync,
+        F: FnOnce() -> T + Send,
+    {
+        LAZIES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(lazy);
+    }
+
+    /// The list is copied so that initializers can register more lazies;
+    /// those are left for the next pass.
+    fn registered() -> Vec<&'static dyn Force> {
+        LAZIES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Forces every registered lazy, in registration order. A panicking
+    /// initializer propagates, leaving the remaining lazies unforced.
+    pub fn force_all() {
+        for lazy in registered() {
+            lazy.force();
+        }
+    }
+
+    /// Like [`force_all`], but spreads the lazies over `threads` threads.
+    /// Lazies that depend on each other are fine: forcing one that another
+    /// thread is already forcing just waits for it.
+    pub fn force_all_parallel(threads: usize) {
+        let lazies = registered();
+        let next = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(|| {
+                    while let Some(lazy) = lazies.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        lazy.force();
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// A list of lazy statics and how far each got, to find out at runtime which
+/// ones were forced, and in what order.
+///
+/// Lazies are listed with [`register`](diagnostics::register), or declared
+/// with `tracked_lazy!` (behind the `ctor` feature) to list themselves.
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics {
+    use crate::sync::{Lazy, OnceBackend, State};
+    use std::panic::Location;
+    use std::sync::{Mutex, PoisonError};
+
+    trait Inspect: Sync {
+        fn info(&self, name: &'static str) -> LazyInfo;
+    }
+
+    impl<T: Send + Sync, F: Send, B: OnceBackend + Sync> Inspect for Lazy<T, F, B> {
+        fn info(&self, name: &'static str) -> LazyInfo {
+            LazyInfo {
+                name,
+                defined_at: Lazy::defined_at(self),
+                state: Lazy::state(self),
+                forced_order: Lazy::forced_order(self),
+            }
+        }
+    }
+
+    static LAZIES: Mutex<Vec<(&'static str, &'static dyn Inspect)>> = Mutex::new(Vec::new());
+
+    /// A registered lazy, as listed by [`lazies`].
+    #[derive(Debug, Clone, Copy)]
+    pub struct LazyInfo {
+        pub name: &'static str,
+        /// Where the lazy was created.
+        pub defined_at: &'static Location<'static>,
+        pub state: State,
+        /// Where the lazy came among all lazies of the process, registered or
+        /// not, in the order they were forced, from 1.
+        pub forced_order: Option<usize>,
+    }
+
+    /// Adds `lazy` to the list under `name`. Registering a lazy twice is
+    /// harmless; it keeps its first name.
+    pub fn register<T, F, B>(name: &'static str, lazy: &'static Lazy<T, F, B>)
+    where
+        T: Send + Sync,
+        F: Send,
+        B: OnceBackend + Sync,
+    {
+        let mut lazies = LAZIES.lock().unwrap_or_else(PoisonError::into_inner);
+        let address = lazy as *const Lazy<T, F, B> as *const ();
+        if !lazies
+            .iter()
+            .any(|&(_, other)| other as *const dyn Inspect as *const () == address)
+        {
+            lazies.push((name, lazy));
+        }
+    }
+
+    /// A snapshot of every registered lazy, in registration order.
+    pub fn lazies() -> Vec<LazyInfo> {
+        let lazies = LAZIES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        lazies.iter().map(|&(name, lazy)| lazy.info(name)).collect()
+    }
+}
+
+/// String interning: equal strings share one leaked `&'static str`.
+pub mod intern {
+    use crate::sync::Lazy;
+    use std::collections::HashSet;
+    use std::sync::{Mutex, PoisonError};
+
+    /// Interned strings are never freed, so an interner is meant for a
+    /// bounded set of strings, like identifiers or metric names.
+    #[derive(Default)]
+    pub struct Interner {
+        strings: Mutex<HashSet<&'static str>>,
+    }
+
+    impl Interner {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn intern(&self, s: &str) -> &'static str {
+            let mut strings = self.strings.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(&interned) = strings.get(s) {
+                return interned;
+            }
+            // The one allocation, both the key and the returned copy.
+            let interned: &'static str = Box::leak(Box::from(s));
+            strings.insert(interned);
+            interned
+        }
+
+        /// The interned copy of `s`, without interning it.
+        pub fn get(&self, s: &str) -> Option<&'static str> {
+            let strings = self.strings.lock().unwrap_or_else(PoisonError::into_inner);
+            strings.get(s).copied()
+        }
+    }
+
+    static GLOBAL: Lazy<Interner> = Lazy::new(Interner::new);
+
+    /// Interns `s` in a process-wide interner.
+    pub fn intern(s: &str) -> &'static str {
+        GLOBAL.intern(s)
+    }
+}
+
+/// Finalizers run at process exit, for lazy statics holding resources such as
+/// temporary directories or log sinks, whose destructors never run.
+///
+/// Finalizers run in registration order when `main` returns or
+/// `std::process::exit` is called, from the C runtime's `atexit` hook. A
+/// panicking finalizer aborts the process there.
+pub mod at_exit {
+    use crate::sync::Lazy;
+    use std::sync::{Mutex, Once, PoisonError};
+
+    type Finalizer = Box<dyn FnOnce() + Send>;
+
+    static FINALIZERS: Mutex<Vec<Finalizer>> = Mutex::new(Vec::new());
+
+    #[cfg(any(unix, windows))]
+    fn install() {
+        extern "C" {
+            fn atexit(f: extern "C" fn()) -> std::os::raw::c_int;
+        }
+
+        extern "C" fn at_exit() {
+            run();
+        }
+
+        static INSTALL: Once = Once::new();
+        // SAFETY:
+        // * `at_exit` is a plain `extern "C" fn` that lives forever
+        INSTALL.call_once(|| unsafe {
+            atexit(at_exit);
+        });
+    }
+
+    /// Elsewhere, call [`run`] before exiting.
+    #[cfg(not(any(unix, windows)))]
+    fn install() {}
+
+    pub fn register<F: FnOnce() + Send + 'static>(f: F) {
+        install();
+        FINALIZERS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(f));
+    }
+
+    /// Registers `f` to run on the value of `lazy` at exit, if it was forced
+    /// by then.
+    pub fn register_lazy<T, F>(lazy: &'static Lazy<T, F>, f: fn(&T))
+    where
+        T: Send + Sync,
+        F: FnOnce() -> T + Send,
+    {
+        register(move || {
+            if let Some(value) = Lazy::get(lazy) {
+                f(value)
+            }
+        })
+    }
+
+    /// Runs the registered finalizers now and forgets them. Ones that they
+    /// register in turn run too.
+    pub fn run() {
+        loop {
+            let finalizers =
+                std::mem::take(&mut *FINALIZERS.lock().unwrap_or_else(PoisonError::into_inner));
+            if finalizers.is_empty() {
+                return;
+            }
+            for f in finalizers {
+                f();
+            }
+        }
+    }
+}
+
+/// Global collections that crates add items to where they define them, e.g.
+/// plugins registering handlers or codecs.
+///
+/// Items are submitted with `submit!` (behind the `ctor` feature) before
+/// `main` runs, and gathered into a slice on first read.
+pub mod collect {
+    use crate::sync::OnceCell;
+    use std::sync::{Mutex, PoisonError};
+
+    pub struct Collection<T> {
+        /// `None` once `items` took the items, so that a late `submit` can
+        /// tell under the same lock.
+        pending: Mutex<Option<Vec<T>>>,
+        items: OnceCell<Box<[T]>>,
+    }
+
+    impl<T> Collection<T> {
+        pub const fn new() -> Self {
+            Self {
+                pending: Mutex::new(Some(Vec::new())),
+                items: OnceCell::new(),
+            }
+        }
+
+        /// Panics if the collection was already read, as the item would
+        /// never be seen.
+        pub fn submit(&self, item: T) {
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            match &mut *pending {
+                Some(pending) => pending.push(item),
+                None => {
+                    drop(pending);
+                    panic!("item submitted after the collection was read")
+                }
+            }
+        }
+
+        /// Every submitted item. Items from one crate come in submission
+        /// order; the order between crates is unspecified.
+        pub fn items(&self) -> &[T] {
+            self.items.get_or_init(|| {
+                let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+                pending.take().unwrap_or_default().into_boxed_slice()
+            })
+        }
+    }
+
+    impl<T> Default for Collection<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+}
+
+/// One global instance per type, for things like metrics recorders or runtime
+/// handles, without every crate declaring its own static.
+pub mod registry {
+    use crate::sync::{Lazy, Memo};
+    use std::any::{Any, TypeId};
+
+    static REGISTRY: Lazy<Memo<TypeId, Box<dyn Any + Send + Sync>>> = Lazy::new(Memo::new);
+
+    /// Returns the instance of `T`, creating it with `f` if there is none yet.
+    ///
+    /// `f` may look up instances of other types, but asking for `T` itself from
+    /// within `f` deadlocks.
+    pub fn get_or_init<T: Any + Send + Sync, F: FnOnce() -> T>(f: F) -> &'static T {
+        let value = REGISTRY.get_or_init(TypeId::of::<T>(), || Box::new(f()));
+        // Keyed by its own type id, so the downcast can't fail.
+        value.downcast_ref().unwrap()
+    }
+
+    pub fn get<T: Any + Send + Sync>() -> Option<&'static T> {
+        REGISTRY.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+}
+
+pub mod raw {
+    use super::UnsafeCell;
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// The cell is empty.
+    pub const UNINIT: u32 = 0;
+    /// A participant is writing the value.
+    pub const WRITING: u32 = 1;
+    /// The value is written and may be read.
+    pub const READY: u32 = 2;
+
+    /// A once cell with a fixed layout, for sharing with C code or placing in
+    /// memory mapped by several processes.
+    ///
+    /// # Layout
+    ///
+    /// `#[repr(C)]`: a `u32` state word at offset 0, followed by the `T`, which
+    /// is at offset 4 rounded up to the alignment of `T`. The state is always
+    /// one of [`UNINIT`], [`WRITING`] or [`READY`]. An all-zero `RawOnceCell`
+    /// is empty, so zero-filled shared memory needs no further setup.
+    ///
+    /// # Protocol
+    ///
+    /// Every participant, Rust or not, must follow it:
+    /// * to write, compare-and-swap the state from `UNINIT` to `WRITING`
+    ///   (acquire), write the value, then store `READY` (release); whoever
+    ///   loses the swap must not touch the value
+    /// * to read, load the state (acquire) and read the value only if it is
+    ///   `READY`
+    /// * a writer that gives up stores `UNINIT` (release) without having
+    ///   written anything
+    ///
+    /// There is no way to park on memory shared between processes portably, so
+    /// threads waiting for a `WRITING` cell yield in a loop. Keep the
+    /// initializers given to [`RawOnceCell::get_or_init`] short.
+    ///
+    /// `T` needs a C-compatible layout itself if C code is to read it.
+    #[repr(C)]
+    pub struct RawOnceCell<T> {
+        state: AtomicU32,
+        value: UnsafeCell<MaybeUninit<T>>,
+    }
+
+    // SAFETY:
+    // * the value is written only by the participant that swapped the state
+    //   from `UNINIT` to `WRITING`, and read only after an `Acquire` load saw
+    //   `READY`, which the writer stores with `Release` once the write is done
+    // * after `READY` the value is never written again, so shared `&T`s are
+    //   fine given `T: Sync`
+    // * the value may be written on one thread and dropped on another, hence
+    //   `T: Send`
+    unsafe impl<T: Send + Sync> Sync for RawOnceCell<T> {}
+
+    impl<T> RawOnceCell<T> {
+        pub const fn new() -> Self {
+            Self {
+                state: AtomicU32::new(UNINIT),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+
+        pub fn get(&self) -> Option<&T> {
+            if self.state.load(Ordering::Acquire) == READY {
+                // SAFETY:
+                // * `READY` is published after the value was written, and the
+                //   value is never written again
+                Some(unsafe { (*self.value.get()).assume_init_ref() })
+            } else {
+                None
+            }
+        }
+
+        pub fn set(&self, value: T) -> Result<(), T> {
+            let mut value = Some(value);
+            self.get_or_init(|| value.take().unwrap());
+            match value {
+                None => Ok(()),
+                Some(value) => Err(value),
+            }
+        }
+
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            let mut f = Some(f);
+            loop {
+                if let Some(value) = self.get() {
+                    return value;
+                }
+                match self.state.compare_exchange(
+                    UNINIT,
+                    WRITING,
+                    Ordering::Acquire,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => {
+                        // Hands the cell back to other participants if `f` panics.
+                        let reset = Reset(&self.state);
+                        let value = (f.take().unwrap())();
+                        // SAFETY:
+                        // * winning the swap gives us exclusive access to the value
+                        unsafe { (*self.value.get()).write(value) };
+                        std::mem::forget(reset);
+                        self.state.store(READY, Ordering::Release);
+                    }
+                    Err(_) => std::thread::yield_now(),
+                }
+            }
+        }
+
+        /// The address of the value, initialized or not, e.g. for a C API
+        /// that fills in an out-parameter. It stays the same for as long as
+        /// the cell lives; writing through it requires a [`try_claim`].
+        ///
+        /// [`try_claim`]: RawOnceCell::try_claim
+        pub fn as_ptr(&self) -> *mut T {
+            self.value.get().cast()
+        }
+
+        /// Moves the cell from `UNINIT` to `WRITING`, returning whether it
+        /// did. Only then may the caller write the value through `as_ptr`,
+        /// and it must finish with [`assume_init`] or [`unclaim`].
+        ///
+        /// [`assume_init`]: RawOnceCell::assume_init
+        /// [`unclaim`]: RawOnceCell::unclaim
+        pub fn try_claim(&self) -> bool {
+            self.state
+                .compare_exchange(UNINIT, WRITING, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        }
+
+        /// Publishes a value written through `as_ptr`.
+        ///
+        /// # Safety
+        ///
+        /// * this caller's `try_claim` succeeded, and it hasn't finished the
+        ///   claim yet
+        /// * a valid `T` was written through `as_ptr`, and the write
+        ///   happens-before this call: it was made on this thread, or on one
+        ///   that synchronized with it, e.g. a C callback that this thread
+        ///   joined or waited for through a lock
+        pub unsafe fn assume_init(&self) {
+            self.state.store(READY, Ordering::Release);
+        }
+
+        /// Gives up a claim without publishing anything.
+        ///
+        /// # Safety
+        ///
+        /// This caller's `try_claim` succeeded, and it hasn't finished the
+        /// claim yet. Anything written through `as_ptr` is forgotten, not
+        /// dropped.
+        pub unsafe fn unclaim(&self) {
+            self.state.store(UNINIT, Ordering::Release);
+        }
+    }
+
+    impl<T> Default for RawOnceCell<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T> Drop for RawOnceCell<T> {
+        fn drop(&mut self) {
+            if *self.state.get_mut() == READY {
+                // SAFETY:
+                // * the value was written, and we have exclusive access
+                unsafe { self.value.get_mut().assume_init_drop() }
+            }
+        }
+    }
+
+    struct Reset<'a>(&'a AtomicU32);
+
+    impl Drop for Reset<'_> {
+        fn drop(&mut self) {
+            self.0.store(UNINIT, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn unsync_works() {
+        let once: unsync::OnceCell<String> = unsync::OnceCell::new();
+        assert!(once.get().is_none());
+        assert!(once.set(String::new()).is_ok());
+        assert!(once.set(String::new()).is_err());
+        assert!(once.get().is_some());
+        assert!(once.get().is_some());
+    }
+
+    #[test]
+    fn sync_works() {
+        use std::sync::Arc;
+
+        let once = Arc::new(sync::OnceCell::new());
+
+        let one = Arc::clone(&once);
+        std::thread::spawn(move || {
+            println!("{:?}", one.set(String::from("Hello")));
+        });
+
+        let two = Arc::clone(&once);
+        std::thread::spawn(move || {
+            println!("{:?}", two.set(String::from("World")));
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        println!("{:?}", once.get());
+    }
+
+    #[test]
+    fn once_init_is_generic_over_cells() {
+        fn first_name<C: OnceInit<Value = String>>(cell: &C) -> &str {
+            cell.get_or_init(|| String::from("Hello"))
+        }
+
+        let once = unsync::OnceCell::new();
+        assert_eq!(first_name(&once), "Hello");
+        assert!(once.set(String::from("World")).is_err());
+
+        let once = sync::OnceCell::new();
+        assert!(once.set(String::from("World")).is_ok());
+        assert_eq!(first_name(&once), "World");
+    }
+
+    #[test]
+    fn sync_get_or_init_racy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Barrier};
+
+        let once = Arc::new(sync::OnceCell::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(2));
+
+        let threads: Vec<_> = (0..2)
+            .map(|i| {
+                let (once, calls, barrier) = (once.clone(), calls.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    *once.get_or_init_racy(|| {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        // Make sure both threads are running their initializer.
+                        barrier.wait();
+                        i
+                    })
+                })
+            })
+            .collect();
+        let seen: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(seen[0], seen[1]);
+        assert_eq!(once.get(), Some(&seen[0]));
+    }
+
+    #[test]
+    fn pinned_access() {
+        use std::marker::PhantomPinned;
+        use std::pin::Pin;
+
+        struct Node {
+            value: u32,
+            _pin: PhantomPinned,
+        }
+        let node = || Node {
+            value: 92,
+            _pin: PhantomPinned,
+        };
+
+        let once = Box::pin(unsync::OnceCell::new());
+        assert!(once.as_ref().get_pin().is_none());
+        let pinned: Pin<&Node> = once.as_ref().get_or_init_pin(node);
+        assert_eq!(pinned.value, 92);
+        assert_eq!(once.as_ref().get_pin().map(|n| n.value), Some(92));
+
+        let once = Box::pin(sync::OnceCell::new());
+        assert_eq!(once.as_ref().get_or_init_pin(node).value, 92);
+        assert_eq!(once.as_ref().get_pin().map(|n| n.value), Some(92));
+    }
+
+    #[test]
+    fn once_any() {
+        let once = sync::OnceAny::new();
+        assert!(once.get::<u32>().is_none());
+        assert!(once.set(92u32).is_ok());
+        assert_eq!(once.set(String::from("hello")), Err(String::from("hello")));
+        assert_eq!(once.get::<u32>(), Some(&92));
+        assert!(once.get::<String>().is_none());
+    }
+
+    #[test]
+    fn sync_state() {
+        use std::sync::mpsc;
+        use std::sync::Arc;
+
+        let once = Arc::new(sync::OnceCell::new());
+        assert_eq!(once.state(), sync::State::Uninit);
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let t = std::thread::spawn({
+            let once = once.clone();
+            move || {
+                once.get_or_init(|| {
+                    started_tx.send(()).unwrap();
+                    finish_rx.recv().unwrap();
+                    92
+                });
+            }
+        });
+        started_rx.recv().unwrap();
+        assert_eq!(once.state(), sync::State::Initializing);
+        assert_eq!(once.get(), None);
+        finish_tx.send(()).unwrap();
+        t.join().unwrap();
+        assert_eq!(once.state(), sync::State::Complete);
+
+        let once = sync::OnceCell::<u32>::new();
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            once.get_or_init(|| panic!("boom"));
+        }));
+        assert!(res.is_err());
+        assert_eq!(once.state(), sync::State::Poisoned);
+    }
+
+    #[test]
+    fn sync_custom_backend() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        // Delegates to the default backend, counting the closures it runs.
+        struct Counting<'a> {
+            inner: sync::DefaultBackend,
+            runs: &'a AtomicUsize,
+        }
+
+        unsafe impl sync::OnceBackend for Counting<'_> {
+            fn is_completed(&self) -> bool {
+                self.inner.is_completed()
+            }
+
+            fn call_once<F: FnOnce()>(&self, f: F) {
+                self.inner.call_once(|| {
+                    self.runs.fetch_add(1, Ordering::Relaxed);
+                    f()
+                })
+            }
+
+            fn call_once_timeout<F: FnOnce()>(&self, f: F, timeout: Duration) -> bool {
+                self.inner.call_once_timeout(
+                    || {
+                        self.runs.fetch_add(1, Ordering::Relaxed);
+                        f()
+                    },
+                    timeout,
+                )
+            }
+
+            fn state(&self) -> sync::State {
+                self.inner.state()
+            }
+        }
+
+        let runs = AtomicUsize::new(0);
+        let once = sync::OnceCell::with_backend(Counting {
+            inner: sync::DefaultBackend::new(),
+            runs: &runs,
+        });
+        assert_eq!(*once.get_or_init(|| 92), 92);
+        assert!(once.set(62).is_err());
+        assert_eq!(*once.get_or_init(|| 0), 92);
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(all(
+        feature = "native-once",
+        any(target_os = "linux", target_os = "android")
+    ))]
+    #[test]
+    fn sync_native_backend() {
+        use std::sync::Arc;
+
+        let once = Arc::new(sync::OnceCell::with_backend(sync::NativeBackend::new()));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let once = once.clone();
+                std::thread::spawn(move || *once.get_or_init(|| i))
+            })
+            .collect();
+        let values: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(values.iter().all(|v| *v == values[0]));
+        assert_eq!(once.state(), sync::State::Complete);
+
+        let once = sync::OnceCell::<u32, _>::with_backend(sync::NativeBackend::new());
+        let init = |f: fn() -> u32| {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *once.get_or_init(f)))
+        };
+        let res = init(|| panic!("boom"));
+        assert_eq!(res.unwrap_err().downcast_ref::<&str>(), Some(&"boom"));
+        assert_eq!(once.state(), sync::State::Poisoned);
+        assert!(init(|| 92).is_err());
+    }
+
+    #[test]
+    fn lazy_clone() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn init() -> String {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            String::from("hello")
+        }
+
+        let lazy = unsync::Lazy::new(init as fn() -> String);
+        let template = lazy.clone();
+        assert_eq!(&*lazy, "hello");
+        let forced = lazy.clone();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(&*forced, "hello");
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(&*template, "hello");
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+        let lazy = sync::Lazy::new(init as fn() -> String);
+        let template = lazy.clone();
+        assert_eq!(sync::Lazy::force(&lazy), "hello");
+        let forced = lazy.clone();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+        assert_eq!(&*forced, "hello");
+        assert_eq!(&*template, "hello");
+        assert_eq!(CALLS.load(Ordering::SeqCst), 4);
+
+        thread_local! {
+            static CLONES_ITSELF: unsync::Lazy<u32> = unsync::Lazy::new(clone_itself);
+        }
+        fn clone_itself() -> u32 {
+            CLONES_ITSELF.with(|lazy| drop(lazy.clone()));
+            92
+        }
+        let err = std::panic::catch_unwind(|| CLONES_ITSELF.with(|lazy| **lazy)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<&str>(),
+            Some(&"Lazy instance cloned while being initialized")
+        );
+    }
+
+    #[test]
+    fn get_or_init_with_flag() {
+        let once = unsync::OnceCell::new();
+        assert_eq!(once.get_or_init_with_flag(|| 92), (&92, true));
+        assert_eq!(once.get_or_init_with_flag(|| 62), (&92, false));
+
+        let once = sync::OnceCell::new();
+        assert!(once.set(92).is_ok());
+        assert_eq!(once.get_or_init_with_flag(|| 62), (&92, false));
+    }
+
+    #[test]
+    fn raw_once_cell() {
+        use std::mem::size_of;
+        use std::sync::Arc;
+
+        assert_eq!(size_of::<raw::RawOnceCell<u32>>(), 8);
+        assert_eq!(size_of::<raw::RawOnceCell<[u64; 2]>>(), 24);
+        let once = raw::RawOnceCell::new();
+        let value: *const [u64; 2] = once.get_or_init(|| [1, 2]);
+        assert_eq!(value as usize - &once as *const _ as usize, 8);
+
+        let once = Arc::new(raw::RawOnceCell::new());
+        assert!(once.get().is_none());
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let once = once.clone();
+                std::thread::spawn(move || *once.get_or_init(|| i))
+            })
+            .collect();
+        let seen: Vec<u32> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(seen.iter().all(|&v| v == seen[0]));
+        assert_eq!(once.set(92), Err(92));
+    }
+
+    #[test]
+    fn memo() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = AtomicUsize::new(0);
+        let square = |n: u64| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            n * n
+        };
+
+        let memo = sync::Memo::new();
+        let first = memo.get_or_init(3, || square(3));
+        assert_eq!(*first, 9);
+        // Grow the map well past its initial capacity.
+        for n in 0..100 {
+            assert_eq!(*memo.get_or_init(n, || square(n)), n * n);
+        }
+        assert_eq!(*first, 9);
+        assert_eq!(memo.get(&3), Some(&9));
+        assert_eq!(memo.get(&100), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn once_macro() {
+        let mut runs = 0;
+        let mut passes = Vec::new();
+        for _ in 0..3 {
+            passes.push(once! { runs += 1; });
+        }
+        assert_eq!(passes, [true, false, false]);
+        assert_eq!(runs, 1);
+
+        // Every call site gets its own flag.
+        assert!(once! {});
+    }
+
+    #[test]
+    fn sync_get_or_init_timeout() {
+        use std::sync::mpsc;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let once = Arc::new(sync::OnceCell::new());
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let t = std::thread::spawn({
+            let once = once.clone();
+            move || {
+                once.get_or_init(|| {
+                    started_tx.send(()).unwrap();
+                    finish_rx.recv().unwrap();
+                    92
+                });
+            }
+        });
+        started_rx.recv().unwrap();
+        let res = once.get_or_init_timeout(|| 62, Duration::from_millis(10));
+        assert_eq!(res, Err(sync::Timeout));
+
+        finish_tx.send(()).unwrap();
+        assert_eq!(
+            once.get_or_init_timeout(|| 62, Duration::from_secs(60)),
+            Ok(&92)
+        );
+        t.join().unwrap();
+
+        let once = sync::OnceCell::new();
+        assert_eq!(once.get_or_init_timeout(|| 62, Duration::ZERO), Ok(&62));
+    }
+
+    #[test]
+    fn once_array() {
+        use std::sync::Arc;
+
+        let array = Arc::new(sync::OnceArray::<String, 8>::new());
+        assert!(array.get(3).is_none());
+        assert!(array.get(8).is_none());
+
+        let threads: Vec<_> = (0..16)
+            .map(|n| {
+                let array = array.clone();
+                std::thread::spawn(move || {
+                    let i = n % 4;
+                    array.get_or_init(i, || format!("slot {}", i)).clone()
+                })
+            })
+            .collect();
+        for (n, t) in threads.into_iter().enumerate() {
+            assert_eq!(t.join().unwrap(), format!("slot {}", n % 4));
+        }
+        assert_eq!(array.get(2).map(String::as_str), Some("slot 2"));
+        assert!(array.get(4).is_none());
+
+        let panicked = std::panic::AssertUnwindSafe(|| array.get_or_init(5, || panic!()));
+        assert!(std::panic::catch_unwind(panicked).is_err());
+        assert!(array.get(5).is_none());
+        assert_eq!(array.get_or_init(5, || "slot 5".to_string()), "slot 5");
+    }
+
+    #[test]
+    fn registry() {
+        #[derive(Debug, PartialEq)]
+        struct Recorder(&'static str);
+        struct Handle(&'static Recorder);
+
+        assert!(registry::get::<Recorder>().is_none());
+        let handle = registry::get_or_init(|| Handle(registry::get_or_init(|| Recorder("first"))));
+        assert_eq!(
+            registry::get_or_init(|| Recorder("second")),
+            &Recorder("first")
+        );
+        assert!(std::ptr::eq(handle.0, registry::get::<Recorder>().unwrap()));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn sync_metrics() {
+        use std::sync::{Arc, Barrier};
+        use std::time::Duration;
+
+        let once = Arc::new(sync::OnceCell::new());
+        assert_eq!(once.metrics(), sync::Metrics::default());
+
+        let barrier = Arc::new(Barrier::new(2));
+        let t = std::thread::spawn({
+            let (once, barrier) = (once.clone(), barrier.clone());
+            move || {
+                once.get_or_init(|| {
+                    barrier.wait();
+                    std::thread::sleep(Duration::from_millis(20));
+                    92
+                });
+            }
+        });
+        barrier.wait();
+        assert_eq!(*once.get_or_init(|| 62), 92);
+        t.join().unwrap();
+
+        let metrics = once.metrics();
+        assert_eq!(metrics.contended, 1);
+        assert!(metrics.init_time.unwrap() >= Duration::from_millis(20));
+
+        static LAZY: sync::Lazy<u32> = sync::Lazy::new(|| 92);
+        assert_eq!(*LAZY, 92);
+        assert_eq!(sync::Lazy::metrics(&LAZY).contended, 0);
+        assert!(sync::Lazy::metrics(&LAZY).init_time.is_some());
+    }
+
+    #[cfg(feature = "init-site")]
+    #[test]
+    fn sync_init_site() {
+        let once = sync::OnceCell::new();
+        assert!(once.init_site().is_none());
+        std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .name(String::from("initializer"))
+                .spawn_scoped(scope, || once.set(92).unwrap())
+                .unwrap();
+        });
+        assert_eq!(once.set(62), Err(62));
+
+        let site = once.init_site().unwrap();
+        assert_eq!(site.thread_name.as_deref(), Some("initializer"));
+        assert_ne!(site.thread_id, std::thread::current().id());
+
+        let err = once.set_traced(62).unwrap_err();
+        assert_eq!(err.value, 62);
+        let debug = format!("{:?}", err);
+        assert!(debug.starts_with("SetError { value: 62, site: Some(InitSite {"));
+        assert!(debug.contains("thread_name: Some(\"initializer\")"));
+    }
+
+    #[test]
+    fn thread_lazy() {
+        use std::cell::Cell;
+
+        thread_lazy! {
+            static ID: String = format!("{:?}", std::thread::current().id());
+            static COUNTER: Cell<u32> = Cell::new(0);
+        }
+
+        let here = ID.with(|id| id.to_string());
+        let there = std::thread::spawn(|| ID.with(|id| id.to_string()))
+            .join()
+            .unwrap();
+        assert_eq!(here, format!("{:?}", std::thread::current().id()));
+        assert_ne!(here, there);
+
+        COUNTER.with(|c| c.set(c.get() + 1));
+        assert_eq!(COUNTER.with(|c| c.get()), 1);
+        assert_eq!(
+            std::thread::spawn(|| COUNTER.with(|c| c.get()))
+                .join()
+                .unwrap(),
+            0
+        );
+    }
+
+    #[cfg(feature = "ctor")]
+    #[test]
+    fn eager_lazy() {
+        eager_lazy! {
+            static EAGER: String = String::from("warm");
+        }
+
+        assert_eq!(sync::Lazy::get(&EAGER).map(String::as_str), Some("warm"));
+    }
+
+    #[test]
+    fn lazy_default() {
+        #[derive(Default)]
+        struct Context {
+            scratch: unsync::Lazy<Vec<u8>>,
+            shared: sync::Lazy<String>,
+        }
+
+        let ctx = Context::default();
+        assert!(ctx.scratch.is_empty());
+        assert_eq!(&*ctx.shared, "");
+    }
+
+    #[test]
+    fn lazy_associated_functions() {
+        // `force` on the target must not be shadowed by the lazy's.
+        struct Engine(u32);
+        impl Engine {
+            fn force(&self) -> u32 {
+                self.0 * 2
+            }
+        }
+
+        let mut lazy = unsync::Lazy::new(|| Engine(46));
+        assert!(unsync::Lazy::get(&lazy).is_none());
+        assert_eq!(lazy.force(), 92);
+        unsync::Lazy::force_mut(&mut lazy).0 = 31;
+        assert_eq!(unsync::Lazy::get(&lazy).map(|e| e.0), Some(31));
+        assert!(unsync::Lazy::get_mut(&mut lazy).is_some());
+
+        let mut lazy = sync::Lazy::new(|| Engine(46));
+        assert!(sync::Lazy::get(&lazy).is_none());
+        sync::Lazy::force_mut(&mut lazy).0 = 31;
+        assert_eq!(lazy.force(), 62);
+        assert_eq!(sync::Lazy::get_mut(&mut lazy).map(|e| e.0), Some(31));
+    }
+
+    #[test]
+    fn sync_auto_traits() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<sync::OnceCell<String>>();
+        assert_send_sync::<sync::Lazy<String>>();
+        // `F` only needs to be `Send`, not `Sync`.
+        assert_send_sync::<sync::Lazy<u32, std::sync::mpsc::Sender<u32>>>();
+
+        static LAZY: sync::Lazy<Vec<u32>> = sync::Lazy::new(|| vec![92]);
+        let t = std::thread::spawn(|| LAZY[0]);
+        assert_eq!(t.join().unwrap(), 92);
+    }
+
+    #[test]
+    fn race_once_box() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Barrier};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Value(usize);
+        impl Drop for Value {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let once = Arc::new(race::OnceBox::new());
+        let barrier = Arc::new(Barrier::new(4));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let (once, barrier) = (once.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    once.get_or_init(|| {
+                        barrier.wait();
+                        Box::new(Value(i))
+                    })
+                    .0
+                })
+            })
+            .collect();
+        let seen: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(seen.iter().all(|&i| i == seen[0]));
+        // All four ran their initializer; the three losers dropped their box.
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+        drop(once);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+
+        let once = race::OnceBox::new();
+        assert_eq!(once.get_or_try_init(|| Err("nope")).err(), Some("nope"));
+        assert!(once.get().is_none());
+        assert_eq!(once.get_or_try_init(|| Ok::<_, ()>(Box::new(92))), Ok(&92));
+        assert_eq!(once.set(Box::new(62)), Err(Box::new(62)));
+    }
+
+    #[test]
+    fn race_once_box_take() {
+        let mut once = race::OnceBox::new();
+        assert_eq!(once.take(), None);
+        assert!(once.set(Box::new(String::from("hello"))).is_ok());
+        assert_eq!(once.take(), Some(Box::new(String::from("hello"))));
+        assert!(once.get().is_none());
+
+        assert!(once.set(Box::new(String::from("world"))).is_ok());
+        assert_eq!(once.into_inner(), Some(Box::new(String::from("world"))));
+    }
+
+    #[test]
+    fn unsync_const_get() {
+        const fn read(cell: &unsync::OnceCell<u32>) -> u32 {
+            match cell.get() {
+                Some(value) => *value,
+                None => 0,
+            }
+        }
+        const VALUE: u32 = read(&unsync::OnceCell::with_value(92));
+        const EMPTY: u32 = read(&unsync::OnceCell::new());
+        assert_eq!((VALUE, EMPTY), (92, 0));
+
+        let cell = unsync::OnceCell::with_value(String::from("hello"));
+        assert!(cell.set(String::new()).is_err());
+    }
+
+    // The check only holds with optimizations, run it with `cargo test --release`.
+    // Recording initializations reads the clock, captures backtraces or calls
+    // an observer, any of which may panic, so those features skip the check.
+    #[cfg(not(any(
+        debug_assertions,
+        feature = "metrics",
+        feature = "init-site",
+        feature = "watchdog",
+        feature = "observe"
+    )))]
+    #[test]
+    fn hot_paths_dont_panic() {
+        struct MayPanic;
+        impl Drop for MayPanic {
+            fn drop(&mut self) {
+                extern "C" {
+                    // Deliberately undefined: the build fails to link if this
+                    // drop survives, i.e. if the guarded code could unwind.
+                    fn once_cell_hot_path_may_panic() -> !;
+                }
+                unsafe { once_cell_hot_path_may_panic() }
+            }
+        }
+        #[inline(always)]
+        fn no_panic<R>(f: impl FnOnce() -> R) -> R {
+            let guard = MayPanic;
+            let res = f();
+            std::mem::forget(guard);
+            res
+        }
+
+        let once = unsync::OnceCell::new();
+        assert_eq!(no_panic(|| once.get()), None);
+        assert_eq!(no_panic(|| once.set(92)), Ok(()));
+        assert_eq!(no_panic(|| once.set(62)), Err(62));
+        let once = unsync::OnceCell::new();
+        assert_eq!(no_panic(|| *once.get_or_init(|| 92)), 92);
+
+        let once = sync::OnceCell::new();
+        assert_eq!(no_panic(|| once.get()), None);
+        assert!(once.set(92).is_ok());
+        assert_eq!(no_panic(|| once.get()), Some(&92));
+
+        // A poisoned cell panics when set, so `set` and `get_or_init` are only
+        // panic-free with a backend that never poisons, and that doesn't
+        // lock a mutex to wait.
+        #[cfg(unix)]
+        {
+            type Backend = sync::PolicyBackend<sync::Spin, sync::Retry>;
+            let once = sync::OnceCell::with_backend(Backend::new());
+            assert_eq!(no_panic(|| once.set(92)), Ok(()));
+            assert_eq!(no_panic(|| once.set(62)), Err(62));
+            let once = sync::OnceCell::with_backend(Backend::new());
+            assert_eq!(no_panic(|| *once.get_or_init(|| 92)), 92);
+        }
+
+        let once = race::OnceBox::new();
+        assert_eq!(no_panic(|| once.get()), None);
+        // Allocating can fail, so the box is made outside.
+        let value = Box::new(92);
+        assert!(no_panic(|| once.set(value)).is_ok());
+    }
+
+    #[test]
+    fn sync_initializer_panic_reaches_waiters() {
+        use std::sync::mpsc;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let once = Arc::new(sync::OnceCell::<u32>::new());
+        let (started_tx, started_rx) = mpsc::channel();
+        let initializer = std::thread::spawn({
+            let once = once.clone();
+            move || {
+                once.get_or_init(|| {
+                    started_tx.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(50));
+                    panic!("boom")
+                });
+            }
+        });
+        started_rx.recv().unwrap();
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let once = once.clone();
+                std::thread::spawn(move || *once.get_or_init(|| 92))
+            })
+            .collect();
+
+        let payload = initializer.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+        for waiter in waiters {
+            let payload = waiter.join().unwrap_err();
+            assert_eq!(
+                payload.downcast_ref::<&str>(),
+                Some(&"OnceCell initializer panicked, the cell is poisoned")
+            );
+        }
+        assert_eq!(once.get(), None);
+        assert_eq!(once.state(), sync::State::Poisoned);
+    }
+
+    #[test]
+    fn lazy_chaining() {
+        use std::cell::Cell;
+
+        let parsed = Cell::new(0);
+        let config = unsync::Lazy::new(|| String::from("4,8,15"));
+        let numbers = unsync::Lazy::and_then(&config, |config| {
+            parsed.set(parsed.get() + 1);
+            config
+                .split(',')
+                .map(|n| n.parse().unwrap())
+                .collect::<Vec<u32>>()
+        });
+        let sum = unsync::Lazy::and_then(&numbers, |numbers| numbers.iter().sum::<u32>());
+        assert!(unsync::Lazy::get(&config).is_none());
+        assert_eq!(*sum, 27);
+        assert_eq!(numbers.len(), 3);
+        assert_eq!(parsed.get(), 1);
+
+        let name = sync::Lazy::new(|| "answer");
+        let value = sync::Lazy::new(|| 42);
+        let both = sync::Lazy::zip(&name, &value);
+        assert_eq!(*both, (&"answer", &42));
+        assert_eq!(sync::Lazy::get(&value), Some(&42));
+    }
+
+    #[test]
+    fn lazy_holds_closure_or_value() {
+        use std::mem::size_of;
+        use std::rc::Rc;
+        use std::sync::Arc;
+
+        let big = [0u8; 256];
+        let lazy = unsync::Lazy::new(move || big);
+        assert!(size_of::<unsync::Lazy<[u8; 256], [u8; 256]>>() < 2 * 256);
+        assert_eq!(lazy.len(), 256);
+
+        let captured = Rc::new(());
+        let lazy = unsync::Lazy::new({
+            let captured = captured.clone();
+            move || drop(captured)
+        });
+        assert_eq!(Rc::strong_count(&captured), 2);
+        unsync::Lazy::force(&lazy);
+        assert_eq!(Rc::strong_count(&captured), 1);
+        drop(unsync::Lazy::<(), _>::new({
+            let captured = captured.clone();
+            move || drop(captured)
+        }));
+        assert_eq!(Rc::strong_count(&captured), 1);
+
+        let captured = Arc::new(());
+        let lazy = sync::Lazy::new({
+            let captured = captured.clone();
+            move || Arc::strong_count(&captured)
+        });
+        let template = lazy.clone();
+        assert_eq!(Arc::strong_count(&captured), 3);
+        assert_eq!(*lazy, 3);
+        assert_eq!(Arc::strong_count(&captured), 2);
+        drop(template);
+        assert_eq!(Arc::strong_count(&captured), 1);
+
+        let lazy = sync::Lazy::new({
+            let captured = captured.clone();
+            move || -> u32 { panic!("{:?}", captured) }
+        });
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy));
+        assert!(result.is_err());
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+
+    // Builds the crate and a probe with optimizations and reads the assembly,
+    // so it needs a `rustc` and is only written against x86-64. Run it with
+    // `cargo test -- --ignored`.
+    #[cfg(all(target_arch = "x86_64", unix))]
+    #[test]
+    #[ignore = "builds the crate with an external rustc"]
+    fn reads_after_init_are_a_single_load() {
+        use std::path::{Path, PathBuf};
+        use std::process::Command;
+
+        struct RemoveOnDrop(PathBuf);
+        impl Drop for RemoveOnDrop {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.0);
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("once_cell-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _remove = RemoveOnDrop(dir.clone());
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+        let run = |args: &[&str]| {
+            let status = Command::new(&rustc)
+                .args(["--edition", "2018", "-C", "opt-level=3"])
+                .args([
+                    "-C",
+                    if cfg!(debug_assertions) {
+                        "debug-assertions=on"
+                    } else {
+                        "debug-assertions=off"
+                    },
+                ])
+                .args(args)
+                .current_dir(&dir)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        // The features of this build, except `derive` and `macros`, which
+        // need the derive crate and only add re-exports.
+        let features = [
+            ("metrics", cfg!(feature = "metrics")),
+            ("init-site", cfg!(feature = "init-site")),
+            ("ctor", cfg!(feature = "ctor")),
+            ("watchdog", cfg!(feature = "watchdog")),
+            ("native-once", cfg!(feature = "native-once")),
+            ("diagnostics", cfg!(feature = "diagnostics")),
+            ("observe", cfg!(feature = "observe")),
+            ("reload", cfg!(feature = "reload")),
+            ("may-dangle", cfg!(feature = "may-dangle")),
+        ];
+        let features: Vec<String> = features
+            .iter()
+            .filter(|&&(_, enabled)| enabled)
+            .map(|(name, _)| format!("feature=\"{}\"", name))
+            .collect();
+        let mut args = vec!["--crate-type=rlib", "--crate-name=once_cell"];
+        for feature in &features {
+            args.extend(["--cfg", feature]);
+        }
+        let lib = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib.rs");
+        args.push(lib.to_str().unwrap());
+        run(&args);
+        std::fs::write(
+            dir.join("probe.rs"),
+            "#[no_mangle]
+            pub fn probe_get(cell: &once_cell::sync::OnceCell<u32>) -> Option<&u32> {
+                cell.get()
+            }
+            #[no_mangle]
+            pub fn probe_deref(lazy: &once_cell::sync::Lazy<u32>) -> u32 {
+                **lazy
+            }",
+        )
+        .unwrap();
+        run(&[
+            "--crate-type=lib",
+            "--emit=asm",
+            "--extern",
+            "once_cell=libonce_cell.rlib",
+            "probe.rs",
+        ]);
+        let asm = std::fs::read_to_string(dir.join("probe.s")).unwrap();
+
+        // Everything up to the first return is the path taken once the cell or
+        // lazy is initialized: a load of the state, a compare, and the value.
+        let fast_path = |symbol: &str| -> Vec<String> {
+            asm.lines()
+                .skip_while(|line| *line != format!("{}:", symbol))
+                .skip(1)
+                .map(str::trim)
+                .filter(|line| !line.starts_with('.'))
+                .take_while(|line| !line.starts_with("ret"))
+                .map(String::from)
+                .collect()
+        };
+        for symbol in ["probe_get", "probe_deref"] {
+            let fast_path = fast_path(symbol);
+            assert!(!fast_path.is_empty(), "{}", asm);
+            let slow = fast_path.iter().any(|line| {
+                ["call", "lock", "fence", "xchg", "jmp"]
+                    .iter()
+                    .any(|op| line.starts_with(op))
+            });
+            assert!(!slow, "{}:\n{}", symbol, fast_path.join("\n"));
+        }
+    }
+
+    // The debugger scripts read private fields by name. This checks their
+    // paths against this test binary's DWARF, so it needs `python3` and
+    // `llvm-dwarfdump`, and a build with debuginfo.
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs python3 and llvm-dwarfdump"]
+    fn debugger_field_paths_exist() {
+        let script = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("debug_metadata/once_cell_pretty_printers.py");
+        let output = std::process::Command::new("python3")
+            .arg(script)
+            .arg(std::env::current_exe().unwrap())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn prewarm() {
+        static FIRST: sync::Lazy<u32> = sync::Lazy::new(|| 4);
+        static SECOND: sync::Lazy<u32> = sync::Lazy::new(|| *FIRST + 8);
+        static THIRD: sync::Lazy<String> = sync::Lazy::new(|| SECOND.to_string());
+        #[cfg(feature = "ctor")]
+        prewarm_lazy! {
+            static REGISTERED: u32 = 15;
+        }
+
+        crate::prewarm::register(&THIRD);
+        crate::prewarm::register(&SECOND);
+        crate::prewarm::register(&FIRST);
+        crate::prewarm::force_all_parallel(2);
+        assert_eq!(sync::Lazy::get(&THIRD).map(String::as_str), Some("12"));
+        #[cfg(feature = "ctor")]
+        assert_eq!(sync::Lazy::get(&REGISTERED), Some(&15));
+    }
+
+    #[test]
+    fn cache_padded() {
+        use std::mem::{align_of, size_of};
+
+        let cells = [
+            crate::CachePadded::new(sync::OnceCell::<u8>::new()),
+            crate::CachePadded::new(sync::OnceCell::new()),
+        ];
+        assert!(align_of::<crate::CachePadded<sync::OnceCell<u8>>>() >= 64);
+        assert_eq!(
+            size_of::<crate::CachePadded<u8>>(),
+            align_of::<crate::CachePadded<u8>>()
+        );
+        let first = &*cells[0] as *const _ as usize;
+        let second = &*cells[1] as *const _ as usize;
+        assert!(second - first >= 64);
+
+        assert_eq!(cells[1].get_or_init(|| 92), &92);
+        let [_, second] = cells;
+        assert_eq!(second.into_inner().get(), Some(&92));
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn sync_watchdog() {
+        use std::sync::{mpsc, Mutex};
+        use std::time::Duration;
+
+        static REPORTS: Mutex<Vec<(Option<String>, Option<String>)>> = Mutex::new(Vec::new());
+        fn report(stuck: &sync::StuckWait) {
+            let name = |thread: &std::thread::Thread| thread.name().map(String::from);
+            let names = (
+                name(&stuck.waiter),
+                stuck.initializer.as_ref().and_then(name),
+            );
+            REPORTS.lock().unwrap().push(names);
+        }
+        sync::set_watchdog(Duration::from_millis(10), report);
+
+        static CELL: sync::OnceCell<u32> = sync::OnceCell::new();
+        let (started, start) = mpsc::channel();
+        let slow = std::thread::Builder::new()
+            .name("slow".into())
+            .spawn(move || {
+                CELL.get_or_init(|| {
+                    started.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(200));
+                    92
+                })
+            })
+            .unwrap();
+        start.recv().unwrap();
+        let waiter = std::thread::Builder::new().name("waiter".into());
+        assert_eq!(
+            *waiter
+                .spawn(|| CELL.get_or_init(|| 0))
+                .unwrap()
+                .join()
+                .unwrap(),
+            92
+        );
+        slow.join().unwrap();
+
+        // Other tests' waits may be reported too.
+        let reports = REPORTS.lock().unwrap();
+        let ours: Vec<_> = reports
+            .iter()
+            .filter(|r| r.0.as_deref() == Some("waiter"))
+            .collect();
+        assert_eq!(ours, [&(Some("waiter".into()), Some("slow".into()))]);
+    }
+
+    #[test]
+    fn race_once_fn() {
+        fn scalar(xs: &[u32]) -> u32 {
+            xs.iter().sum()
+        }
+        fn unrolled(xs: &[u32]) -> u32 {
+            xs.chunks(2).map(|c| c.iter().sum::<u32>()).sum()
+        }
+
+        // SAFETY:
+        // * a function pointer type
+        let once = unsafe { race::OnceFn::<fn(&[u32]) -> u32>::new_unchecked() };
+        assert!(once.get().is_none());
+        assert_eq!(once.get_or_select(|| unrolled)(&[1, 2, 3]), 6);
+        assert!(once.set(scalar).is_err());
+        assert_eq!(
+            once.get().unwrap() as usize,
+            unrolled as fn(&[u32]) -> u32 as usize
+        );
+
+        extern "C" fn answer() -> i32 {
+            42
+        }
+        static ANSWER: race::OnceFn<extern "C" fn() -> i32> = race::OnceFn::new();
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| ANSWER.get_or_select(|| answer)()))
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), 42);
+        }
+    }
+
+    #[cfg(feature = "observe")]
+    #[test]
+    fn observer() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::sync::Mutex;
+        use sync::InitEventKind;
+
+        type Event = (&'static str, Option<String>, &'static str);
+        static EVENTS: Mutex<Vec<(usize, Event)>> = Mutex::new(Vec::new());
+        fn observe(event: &sync::InitEvent) {
+            let kind = match event.kind {
+                InitEventKind::Start => "start",
+                InitEventKind::Finish { .. } => "finish",
+                InitEventKind::Panic { .. } => "panic",
+            };
+            let thread = event.thread.name().map(String::from);
+            let event = (event.cell, (event.cell_type, thread, kind));
+            EVENTS.lock().unwrap().push(event);
+        }
+        sync::set_observer(observe);
+        // Other tests' cells are observed too.
+        let events = |cell: usize| -> Vec<Event> {
+            let events = EVENTS.lock().unwrap();
+            let events = events.iter().filter(|event| event.0 == cell);
+            events.map(|event| event.1.clone()).collect()
+        };
+
+        let cell = sync::OnceCell::<u32>::new();
+        std::thread::scope(|scope| {
+            let named = std::thread::Builder::new().name("observed".into());
+            named
+                .spawn_scoped(scope, || cell.get_or_init(|| 92))
+                .unwrap();
+        });
+        cell.get_or_init(|| unreachable!());
+        let (name, thread) = (
+            std::any::type_name_of_val(&cell),
+            Some("observed".to_string()),
+        );
+        assert_eq!(
+            events(&cell as *const _ as usize),
+            [(name, thread.clone(), "start"), (name, thread, "finish")]
+        );
+
+        let set = sync::OnceCell::new();
+        set.set(92).unwrap();
+        assert_eq!(events(&set as *const _ as usize), []);
+
+        let lazy = sync::Lazy::<u32>::new(|| panic!());
+        assert!(catch_unwind(AssertUnwindSafe(|| sync::Lazy::force(&lazy))).is_err());
+        let (name, thread) = (
+            std::any::type_name_of_val(&lazy),
+            std::thread::current().name().map(String::from),
+        );
+        assert_eq!(
+            events(&lazy as *const _ as usize),
+            [(name, thread.clone(), "start"), (name, thread, "panic")]
+        );
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn diagnostics_lazies() {
+        use crate::diagnostics::{self, LazyInfo};
+
+        static FIRST: sync::Lazy<u32> = sync::Lazy::new(|| 1);
+        static SECOND: sync::Lazy<u32> = sync::Lazy::new(|| *FIRST + 1);
+        static IDLE: sync::Lazy<u32> = sync::Lazy::new(|| 0);
+        let defined = line!() - 3;
+        diagnostics::register("tests::SECOND", &SECOND);
+        diagnostics::register("tests::FIRST", &FIRST);
+        diagnostics::register("tests::IDLE", &IDLE);
+        diagnostics::register("tests::FIRST again", &FIRST);
+        assert_eq!(*SECOND, 2);
+
+        let find = |name: &str| -> Vec<LazyInfo> {
+            let lazies = diagnostics::lazies().into_iter();
+            lazies.filter(|lazy| lazy.name == name).collect()
+        };
+        let (first, second, idle) = (
+            find("tests::FIRST"),
+            find("tests::SECOND"),
+            find("tests::IDLE"),
+        );
+        assert!(find("tests::FIRST again").is_empty());
+        assert_eq!(first[0].state, sync::State::Complete);
+        assert_eq!(idle[0].state, sync::State::Uninit);
+        assert_eq!(idle[0].forced_order, None);
+        // `SECOND` started first, but `FIRST` finished first.
+        assert!(first[0].forced_order < second[0].forced_order);
+        let lines: Vec<u32> = [&first, &second, &idle]
+            .iter()
+            .map(|lazy| lazy[0].defined_at.line())
+            .collect();
+        assert_eq!(lines, [defined, defined + 1, defined + 2]);
+        assert_eq!(idle[0].defined_at.file(), file!());
+
+        #[cfg(feature = "ctor")]
+        {
+            crate::tracked_lazy! {
+                static TRACKED: u32 = 7;
+            }
+            let tracked = find("once_cell::tests::TRACKED");
+            assert_eq!(tracked[0].state, sync::State::Uninit);
+            assert_eq!(*TRACKED, 7);
+            let tracked = find("once_cell::tests::TRACKED");
+            assert_eq!(tracked[0].state, sync::State::Complete);
+        }
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn sync_async_check() {
+        use std::sync::Mutex;
+
+        static REPORTS: Mutex<Vec<(u32, u32)>> = Mutex::new(Vec::new());
+        fn on_async_worker() -> bool {
+            std::thread::current().name() == Some("async-worker")
+        }
+        fn report(force: &sync::AsyncForce) {
+            let lines = (force.defined_at.line(), force.forced_at.line());
+            REPORTS.lock().unwrap().push(lines);
+        }
+        sync::set_async_check(on_async_worker, report);
+
+        let (defined, lazy) = (line!(), sync::Lazy::new(|| 92));
+        let clone = lazy.clone();
+        let lazy = &lazy;
+        std::thread::scope(|scope| {
+            let worker = std::thread::Builder::new().name("async-worker".into());
+            let forced = worker
+                .spawn_scoped(scope, || (line!(), **lazy))
+                .unwrap()
+                .join()
+                .unwrap();
+            assert_eq!(*REPORTS.lock().unwrap(), [(defined, forced.0)]);
+        });
+        assert_eq!(*sync::Lazy::force(lazy), 92);
+        assert_eq!(REPORTS.lock().unwrap().len(), 1);
+
+        std::thread::scope(|scope| {
+            let worker = std::thread::Builder::new().name("async-worker".into());
+            let forced = worker
+                .spawn_scoped(scope, || (line!(), *clone))
+                .unwrap()
+                .join()
+                .unwrap();
+            assert_eq!(REPORTS.lock().unwrap()[1], (defined, forced.0));
+        });
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn once_attribute() {
+        use std::cell::Cell;
+
+        struct Parser<'a> {
+            source: &'a str,
+            parses: Cell<usize>,
+            tokens: unsync::OnceCell<Vec<&'a str>>,
+            cache: sync::OnceCell<String>,
+        }
+
+        impl<'a> Parser<'a> {
+            #[crate::macros::once]
+            pub fn tokens(&self) -> &Vec<&'a str> {
+                self.parses.set(self.parses.get() + 1);
+                self.source.split(' ').collect()
+            }
+
+            #[crate::macros::once(field = cache)]
+            fn upper(&self) -> &String {
+                self.source.to_uppercase()
+            }
+        }
+
+        let parser = Parser {
+            source: "a b c",
+            parses: Cell::new(0),
+            tokens: unsync::OnceCell::new(),
+            cache: sync::OnceCell::new(),
+        };
+        assert_eq!(parser.tokens(), &["a", "b", "c"]);
+        assert_eq!(parser.tokens().len(), 3);
+        assert_eq!(parser.parses.get(), 1);
+        assert_eq!(parser.upper(), "A B C");
+        assert!(std::ptr::eq(parser.upper(), parser.cache.get().unwrap()));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derive_singleton() {
+        #[derive(Default, crate::Singleton)]
+        struct Counters {
+            hits: std::sync::atomic::AtomicUsize,
+        }
+
+        #[derive(crate::Singleton)]
+        #[singleton(constructor = Named::new)]
+        pub(crate) struct Named(&'static str);
+
+        impl Named {
+            fn new() -> Self {
+                Named("primary")
+            }
+        }
+
+        Counters::instance()
+            .hits
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        assert!(std::ptr::eq(Counters::instance(), Counters::instance()));
+        assert_eq!(
+            Counters::instance()
+                .hits
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+        assert_eq!(Named::instance().0, "primary");
+    }
+
+    #[test]
+    fn race_once_slice() {
+        static TABLE: race::OnceSlice<u16> = race::OnceSlice::new();
+        assert!(TABLE.get().is_none());
+
+        let threads: Vec<_> = (0..4u16)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    TABLE
+                        .get_or_init(|| Box::leak(vec![i; 3].into_boxed_slice()))
+                        .to_vec()
+                })
+            })
+            .collect();
+        let tables: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(tables.iter().all(|t| t == TABLE.get().unwrap()));
+        assert!(TABLE.set(&[]).is_err());
+
+        let empty = race::OnceSlice::<u16>::new();
+        assert!(empty.set(&[]).is_ok());
+        assert_eq!(empty.get(), Some(&[][..]));
+    }
+
+    #[cfg(feature = "reload")]
+    #[test]
+    fn reloadable_lazy() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        static GENERATION: AtomicU32 = AtomicU32::new(0);
+        static CONFIG: crate::reload::ReloadableLazy<u32> =
+            crate::reload::ReloadableLazy::new(|| GENERATION.fetch_add(1, Ordering::SeqCst));
+
+        assert_eq!(CONFIG.epoch(), 0);
+        let first = CONFIG.get();
+        assert_eq!(*first, 0);
+        assert_eq!(*CONFIG.get(), 0);
+        assert_eq!(*CONFIG.reload(), 1);
+        assert_eq!(*CONFIG.get(), 1);
+        assert_eq!(*first, 0);
+
+        CONFIG.invalidate();
+        assert_eq!(CONFIG.epoch(), 2);
+        assert_eq!(*CONFIG.get(), 2);
+        assert_eq!(CONFIG.epoch(), 3);
+
+        // Readers that never stop don't hold up reloads.
+        let lazy = crate::reload::ReloadableLazy::new(|| vec![92; 16]);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        assert_eq!(lazy.get()[15], 92);
+                    }
+                });
+            }
+            for _ in 0..100 {
+                lazy.reload();
+            }
+            lazy.invalidate();
+            done.store(true, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn lazy_spawn() {
+        use std::sync::mpsc;
+
+        let (go, wait) = mpsc::channel::<()>();
+        let lazy = sync::Lazy::spawn(move || {
+            wait.recv().unwrap();
+            92
+        });
+        assert!(sync::Lazy::get(&lazy).is_none());
+        go.send(()).unwrap();
+        assert_eq!(*sync::Lazy::force(&lazy), 92);
+    }
+
+    #[test]
+    fn lazy_try_deref() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let lazy = unsync::Lazy::new(|| 92);
+        assert_eq!(unsync::Lazy::try_deref(&lazy), Ok(&92));
+        let lazy = unsync::Lazy::<u32>::new(|| panic!("boom"));
+        assert!(catch_unwind(AssertUnwindSafe(|| unsync::Lazy::try_deref(&lazy))).is_err());
+        assert_eq!(
+            unsync::Lazy::try_deref(&lazy),
+            Err(crate::LazyError::Poisoned)
+        );
+        thread_local! {
+            static REENTRANT: unsync::Lazy<Option<crate::LazyError>> = unsync::Lazy::new(|| {
+                REENTRANT.with(|lazy| unsync::Lazy::try_deref(lazy).err())
+            });
+        }
+        let err = REENTRANT.with(|lazy| *unsync::Lazy::force(lazy));
+        assert_eq!(err, Some(crate::LazyError::Reentrant));
+
+        let lazy = sync::Lazy::<u32>::new(|| panic!("boom"));
+        assert!(catch_unwind(AssertUnwindSafe(|| sync::Lazy::try_deref(&lazy))).is_err());
+        assert_eq!(
+            sync::Lazy::try_deref(&lazy),
+            Err(crate::LazyError::Poisoned)
+        );
+        assert!(catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+        let lazy = sync::Lazy::new(|| 92);
+        assert_eq!(sync::Lazy::try_deref(&lazy), Ok(&92));
+    }
+
+    #[test]
+    fn race_once_non_zero() {
+        use std::num::NonZeroUsize;
+        use std::ptr::NonNull;
+
+        let once = race::OnceNonZeroUsize::new();
+        assert_eq!(once.get(), None);
+        let one = NonZeroUsize::new(1).unwrap();
+        let two = NonZeroUsize::new(2).unwrap();
+        assert_eq!(once.get_or_init(|| one), one);
+        assert_eq!(once.set(two), Err(two));
+        assert_eq!(once.get_or_init(|| two), one);
+
+        static VALUE: u32 = 92;
+        let once = race::OnceNonNull::<u32>::new();
+        assert!(once.get().is_none());
+        let ptr = NonNull::from(&VALUE);
+        assert!(once.set(ptr).is_ok());
+        assert_eq!(once.get_or_init(NonNull::dangling), ptr);
+        assert_eq!(unsafe { *once.get().unwrap().as_ref() }, 92);
+    }
+
+    // Signal numbers differ between architectures; these are the generic ones.
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn race_in_signal_handler() {
+        use std::num::NonZeroUsize;
+        use std::os::raw::c_int;
+
+        const SIGUSR1: c_int = 10;
+        extern "C" {
+            fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+            fn raise(signum: c_int) -> c_int;
+        }
+
+        static FIRST: race::OnceNonZeroUsize = race::OnceNonZeroUsize::new();
+        static SEEN: race::OnceNonZeroUsize = race::OnceNonZeroUsize::new();
+        static HANDLED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        extern "C" fn handler(_: c_int) {
+            let n = HANDLED.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            let value = FIRST.get_or_init(|| NonZeroUsize::new(n).unwrap());
+            let _ = SEEN.set(value);
+        }
+
+        unsafe {
+            assert_ne!(signal(SIGUSR1, handler), usize::MAX);
+            assert_eq!(raise(SIGUSR1), 0);
+            assert_eq!(raise(SIGUSR1), 0);
+        }
+        assert_eq!(HANDLED.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert_eq!(FIRST.get().map(NonZeroUsize::get), Some(1));
+        assert_eq!(SEEN.get().map(NonZeroUsize::get), Some(1));
+    }
+
+    #[test]
+    fn init_group() {
+        use std::sync::mpsc;
+
+        static GROUP: sync::InitGroup = sync::InitGroup::new();
+        static SCHEMA: sync::GroupCell<String> = sync::GroupCell::new(&GROUP);
+        static VERSION: sync::GroupCell<u32> = sync::GroupCell::new(&GROUP);
+        static UNSET: sync::GroupCell<u32> = sync::GroupCell::new(&GROUP);
+
+        let (set, check) = mpsc::channel();
+        let (checked, resume) = mpsc::channel();
+        let writer = std::thread::spawn(move || {
+            GROUP.get_or_init(|init| {
+                SCHEMA.set(init, String::from("v3"));
+                set.send(()).unwrap();
+                resume.recv().unwrap();
+                VERSION.set(init, 3);
+            })
+        });
+        check.recv().unwrap();
+        assert!(!GROUP.is_initialized());
+        assert!(SCHEMA.get().is_none());
+        checked.send(()).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(SCHEMA.get().map(String::as_str), Some("v3"));
+        assert_eq!(VERSION.get(), Some(&3));
+        assert_eq!(UNSET.get(), None);
+        GROUP.get_or_init(|_| unreachable!());
+
+        let other = sync::InitGroup::new();
+        let res = std::panic::catch_unwind(|| other.get_or_init(|init| VERSION.set(init, 4)));
+        assert!(res.is_err());
+        assert_eq!(VERSION.get(), Some(&3));
+    }
+
+    #[test]
+    fn intern() {
+        let interner = crate::intern::Interner::new();
+        assert_eq!(interner.get("requests"), None);
+        let first = interner.intern("requests");
+        let second = interner.intern(&String::from("requests"));
+        assert!(std::ptr::eq(first, second));
+        assert_eq!(
+            interner.get("requests").map(str::as_ptr),
+            Some(first.as_ptr())
+        );
+        assert!(!std::ptr::eq(first, interner.intern("errors")));
+
+        let global = crate::intern::intern("requests");
+        assert_eq!(global, "requests");
+        assert!(std::ptr::eq(global, crate::intern::intern("requests")));
+    }
+
+    #[test]
+    fn leak() {
+        let once = Box::new(unsync::OnceCell::new());
+        once.get_or_init(|| 92);
+        assert_eq!(once.leak(), Some(&92));
+        assert_eq!(Box::new(unsync::OnceCell::<u32>::new()).leak(), None);
+
+        let once = Box::new(sync::OnceCell::new());
+        once.get_or_init(|| String::from("shared"));
+        let shared: &'static String = once.leak().unwrap();
+        assert_eq!(std::thread::spawn(move || shared.len()).join().unwrap(), 6);
+
+        let once = race::OnceBox::new();
+        once.get_or_init(|| Box::new(92));
+        assert_eq!(once.leak(), Some(&92));
+        assert_eq!(race::OnceBox::<u32>::new().leak(), None);
+    }
+
+    #[test]
+    fn sync_lock_backend() {
+        use std::sync::{Arc, Condvar, Mutex};
+        use std::time::{Duration, Instant};
+
+        // Stands in for an RTOS mutex.
+        #[derive(Default)]
+        struct Lock {
+            held: Mutex<bool>,
+            released: Condvar,
+        }
+
+        unsafe impl sync::RawLock for Lock {
+            fn lock(&self) {
+                let mut held = self.held.lock().unwrap();
+                while *held {
+                    held = self.released.wait(held).unwrap();
+                }
+                *held = true;
+            }
+
+            fn try_lock_for(&self, timeout: Duration) -> bool {
+                let deadline = Instant::now() + timeout;
+                let mut held = self.held.lock().unwrap();
+                while *held {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    held = self.released.wait_timeout(held, deadline - now).unwrap().0;
+                }
+                *held = true;
+                true
+            }
+
+            fn unlock(&self) {
+                *self.held.lock().unwrap() = false;
+                self.released.notify_one();
+            }
+        }
+
+        let once = Arc::new(sync::OnceCell::with_backend(sync::LockBackend::new(
+            Lock::default(),
+        )));
+        let (started, start) = std::sync::mpsc::channel();
+        let slow = {
+            let once = once.clone();
+            std::thread::spawn(move || {
+                *once.get_or_init(|| {
+                    started.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(50));
+                    92
+                })
+            })
+        };
+        start.recv().unwrap();
+        assert_eq!(once.state(), sync::State::Initializing);
+        assert_eq!(
+            once.get_or_init_timeout(|| 0, Duration::from_millis(1)),
+            Err(sync::Timeout)
+        );
+        assert_eq!(*once.get_or_init(|| 0), 92);
+        assert_eq!(slow.join().unwrap(), 92);
+        assert_eq!(once.set(62), Err(62));
+    }
+
+    #[test]
+    fn get_or_init_with() {
+        struct Config {
+            name: String,
+            cache: unsync::OnceCell<usize>,
+        }
+
+        let mut config = Config {
+            name: "once".to_string(),
+            cache: unsync::OnceCell::new(),
+        };
+        let mut calls = 0;
+        let len = *config
+            .cache
+            .get_or_init_with((&config.name, &mut calls), |(name, calls)| {
+                *calls += 1;
+                name.len()
+            });
+        assert_eq!(len, 4);
+        assert_eq!(calls, 1);
+        config.name.clear();
+        assert_eq!(
+            config
+                .cache
+                .get_or_init_with(&config.name, |name| name.len()),
+            &4
+        );
+
+        let cell = sync::OnceCell::new();
+        assert_eq!(cell.get_or_init_with(2, |x| x * 46), &92);
+        assert_eq!(cell.get_or_init_with(0, |x| x), &92);
+    }
+
+    #[test]
+    fn race_traits() {
+        use std::num::NonZeroUsize;
+        use std::ptr::NonNull;
+
+        #[derive(Debug, Default, PartialEq)]
+        struct Caches {
+            boxed: race::OnceBox<u32>,
+            table: race::OnceSlice<u8>,
+            id: race::OnceNonZeroUsize,
+            ptr: race::OnceNonNull<u8>,
+            hook: race::OnceFn<fn() -> u32>,
+        }
+
+        fn hook() -> u32 {
+            92
+        }
+
+        let empty = Caches::default();
+        assert_eq!(
+            format!("{:?}", empty),
+            "Caches { boxed: OnceBox(None), table: OnceSlice(None), id: OnceNonZeroUsize(None), \
+             ptr: OnceNonNull(None), hook: OnceFn(None) }"
+        );
+
+        let byte = Box::leak(Box::new(0u8));
+        let full = Caches {
+            boxed: Box::new(92).into(),
+            table: (&[1u8, 2][..]).into(),
+            id: NonZeroUsize::new(7).unwrap().into(),
+            ptr: NonNull::from(&mut *byte).into(),
+            hook: race::OnceFn::from(hook as fn() -> u32),
+        };
+        assert_ne!(full, empty);
+        assert_eq!(full.boxed.get(), Some(&92));
+        assert_eq!(format!("{:?}", full.table), "OnceSlice(Some([1, 2]))");
+        assert_eq!(full.id.get().map(NonZeroUsize::get), Some(7));
+        assert_eq!(full.hook.get().map(|f| f()), Some(92));
+
+        let same = Caches::default();
+        assert!(same.boxed.set(Box::new(92)).is_ok());
+        assert!(same.table.set(&[1, 2]).is_ok());
+        assert!(same.id.set(NonZeroUsize::new(7).unwrap()).is_ok());
+        assert!(same.ptr.set(NonNull::from(&mut *byte)).is_ok());
+        assert!(same.hook.set(full.hook.get().unwrap()).is_ok());
+        assert_eq!(same, full);
+    }
+
+    #[test]
+    fn collect() {
+        use crate::collect::Collection;
+
+        type Handler = (&'static str, fn(u32) -> u32);
+        static HANDLERS: Collection<Handler> = Collection::new();
+
+        #[cfg(feature = "ctor")]
+        crate::submit!(HANDLERS, ("double", |x| x * 2));
+        #[cfg(not(feature = "ctor"))]
+        HANDLERS.submit(("double", |x| x * 2));
+        HANDLERS.submit(("inc", |x| x + 1));
+
+        let names: Vec<_> = HANDLERS.items().iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, ["double", "inc"]);
+        assert_eq!(HANDLERS.items().iter().fold(45, |x, (_, f)| f(x)), 91);
+        let late = std::panic::catch_unwind(|| HANDLERS.submit(("late", |x| x)));
+        assert!(late.is_err());
+        assert_eq!(HANDLERS.items().len(), 2);
+
+        // Every submit racing the first read is either seen or rejected.
+        let numbers = &Collection::new();
+        let accepted: usize = std::thread::scope(|s| {
+            let submitters: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let submit = |n| std::panic::AssertUnwindSafe(move || numbers.submit(n));
+                        (0..)
+                            .take_while(|&n| std::panic::catch_unwind(submit(n)).is_ok())
+                            .count()
+                    })
+                })
+                .collect();
+            std::thread::yield_now();
+            numbers.items();
+            submitters.into_iter().map(|t| t.join().unwrap()).sum()
+        });
+        assert_eq!(numbers.items().len(), accepted);
+    }
+
+    #[test]
+    fn lazy_arc() {
+        use std::sync::Arc;
+
+        struct Server {
+            config: sync::LazyArc<String, Box<dyn FnOnce() -> String + Send>>,
+        }
+
+        let name = String::from("db");
+        let server = Server {
+            config: sync::LazyArc::new(Box::new(move || format!("{}.conf", name))),
+        };
+        assert!(sync::LazyArc::get(&server.config).is_none());
+        let task = {
+            let config = sync::LazyArc::arc(&server.config);
+            std::thread::spawn(move || config.len())
+        };
+        assert_eq!(task.join().unwrap(), 7);
+        assert_eq!(&*server.config, "db.conf");
+
+        let first = sync::LazyArc::get(&server.config).unwrap();
+        drop(server);
+        assert_eq!(Arc::strong_count(&first), 1);
+        assert_eq!(*first, "db.conf");
+
+        let empty: sync::LazyArc<Vec<u8>> = Default::default();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn at_exit() {
+        use std::sync::Mutex;
+
+        static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static SINK: sync::Lazy<String> = sync::Lazy::new(|| "sink".to_string());
+        static UNUSED: sync::Lazy<String> = sync::Lazy::new(|| "unused".to_string());
+
+        fn flush(name: &String) {
+            LOG.lock().unwrap().push(format!("flush {}", name));
+        }
+
+        crate::at_exit::register_lazy(&SINK, flush);
+        crate::at_exit::register_lazy(&UNUSED, flush);
+        crate::at_exit::register(|| {
+            LOG.lock().unwrap().push("first".to_string());
+            crate::at_exit::register(|| LOG.lock().unwrap().push("nested".to_string()));
+        });
+        assert_eq!(SINK.len(), 4);
+
+        crate::at_exit::run();
+        assert_eq!(*LOG.lock().unwrap(), ["flush sink", "first", "nested"]);
+    }
+
+    #[cfg(feature = "may-dangle")]
+    #[test]
+    fn may_dangle() {
+        // Declared before what they borrow, so dropped after it, as dropck
+        // allows for `Option`.
+        let (unsync_lazy, sync_lazy, boxed);
+        let name = String::from("cell");
+        unsync_lazy = unsync::Lazy::new(|| &name);
+        sync_lazy = sync::Lazy::new(|| &name);
+        boxed = race::OnceBox::new();
+        boxed.set(Box::new(&name)).unwrap();
+        assert_eq!(**unsync_lazy, "cell");
+        assert_eq!(**sync_lazy, "cell");
+        assert_eq!(**boxed.get().unwrap(), "cell");
+    }
+
+    #[test]
+    fn unsync_shorten_by_value() {
+        fn shorten<'a>(cell: unsync::OnceCell<&'static str>) -> unsync::OnceCell<&'a str> {
+            cell.into_inner()
+                .map_or_else(unsync::OnceCell::new, unsync::OnceCell::with_value)
+        }
+
+        let local = String::from("short");
+        let cell = shorten(unsync::OnceCell::with_value("long"));
+        assert_eq!(cell.set(&local), Err(&*local));
+        assert_eq!(cell.into_inner(), Some("long"));
+        assert_eq!(shorten(unsync::OnceCell::new()).into_inner(), None);
+    }
+
+    #[test]
+    fn once_word() {
+        use std::mem::size_of;
+        use std::num::NonZeroUsize;
+        use std::sync::Barrier;
+
+        assert_eq!(
+            size_of::<sync::OnceWord<NonZeroUsize>>(),
+            size_of::<usize>()
+        );
+        assert_eq!(
+            size_of::<sync::OnceWord<&'static str>>(),
+            size_of::<usize>()
+        );
+
+        static PAGE_SIZE: sync::OnceWord<NonZeroUsize> = sync::OnceWord::new();
+        let barrier = Barrier::new(4);
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    barrier.wait();
+                    let size = PAGE_SIZE.get_or_init(|| {
+                        calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        NonZeroUsize::new(4096).unwrap()
+                    });
+                    assert_eq!(size.get(), 4096);
+                });
+            }
+        });
+        assert_eq!(calls.into_inner(), 1);
+
+        static GREETING: sync::OnceWord<&'static String> = sync::OnceWord::new();
+        let panicked = std::panic::catch_unwind(|| GREETING.get_or_init(|| panic!()));
+        assert!(panicked.is_err());
+        assert_eq!(GREETING.get(), None);
+        let hello: &'static String = Box::leak(Box::new(String::from("hello")));
+        assert_eq!(GREETING.get_or_init(move || hello), "hello");
+        assert!(GREETING.set(hello).is_err());
+    }
+
+    #[test]
+    fn get_mut_or_init() {
+        let mut cell = unsync::OnceCell::new();
+        assert!(cell
+            .get_mut_or_try_init(|| "x".parse().map(|n: u32| vec![n]))
+            .is_err());
+        assert_eq!(cell.get(), None);
+        cell.get_mut_or_init(Vec::new).push(1);
+        cell.get_mut_or_init(|| unreachable!()).push(2);
+        assert_eq!(cell.get(), Some(&vec![1, 2]));
+
+        let mut cell = sync::OnceCell::new();
+        assert!(cell.get_mut_or_try_init(|| Err::<String, _>(())).is_err());
+        *cell
+            .get_mut_or_try_init(|| Ok::<_, ()>(String::from("a")))
+            .unwrap() += "b";
+        cell.get_mut_or_init(String::new).push('c');
+        assert_eq!(cell.get().map(String::as_str), Some("abc"));
+    }
+
+    #[test]
+    fn get_or_set() {
+        use std::sync::Arc;
+
+        let cell = unsync::OnceCell::new();
+        assert_eq!(cell.get_or_set(1), &1);
+        assert_eq!(cell.get_or_set(2), &1);
+
+        let cell = sync::OnceCell::new();
+        let value = Arc::new(());
+        assert!(Arc::ptr_eq(cell.get_or_set(value.clone()), &value));
+        assert!(Arc::ptr_eq(cell.get_or_set(Arc::new(())), &value));
+        assert_eq!(Arc::strong_count(&value), 2);
+    }
+
+    #[test]
+    fn lazy_preinit() {
+        static CLIENT: sync::Lazy<String> = sync::Lazy::new(|| "real".to_string());
+        assert_eq!(sync::Lazy::preinit(&CLIENT, "mock".to_string()), Ok(()));
+        assert_eq!(&*CLIENT, "mock");
+        assert_eq!(
+            sync::Lazy::preinit(&CLIENT, "late".to_string()),
+            Err("late".to_string())
+        );
+
+        let forced = sync::Lazy::new(|| 1);
+        assert_eq!(*forced, 1);
+        assert_eq!(sync::Lazy::preinit(&forced, 2), Err(2));
+
+        let dropped = std::rc::Rc::new(());
+        let lazy = unsync::Lazy::new({
+            let dropped = dropped.clone();
+            move || {
+                drop(dropped);
+                0
+            }
+        });
+        assert_eq!(unsync::Lazy::preinit(&lazy, 92), Ok(()));
+        assert_eq!(std::rc::Rc::strong_count(&dropped), 1);
+        assert_eq!(*lazy, 92);
+        assert_eq!(unsync::Lazy::preinit(&lazy, 0), Err(0));
+    }
+
+    #[test]
+    fn is_poisoned() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let cell = sync::OnceCell::<u32>::new();
+        assert!(!cell.is_poisoned());
+        assert!(catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!()))).is_err());
+        assert!(cell.is_poisoned());
+
+        let lazy = sync::Lazy::<u32>::new(|| panic!());
+        assert!(!sync::Lazy::is_poisoned(&lazy));
+        assert!(catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+        assert!(sync::Lazy::is_poisoned(&lazy));
+
+        let lazy = unsync::Lazy::<u32>::new(|| panic!());
+        assert!(catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+        assert!(unsync::Lazy::is_poisoned(&lazy));
+        let fine = unsync::Lazy::new(|| 1);
+        assert_eq!(*fine, 1);
+        assert!(!unsync::Lazy::is_poisoned(&fine));
+    }
+
+    #[test]
+    fn raw_out_parameter() {
+        use crate::raw::RawOnceCell;
+
+        // Stands in for a C function filling in an out-parameter.
+        unsafe extern "C" fn fill(out: *mut u64) -> i32 {
+            unsafe { out.write(92) };
+            0
+        }
+
+        let cell = RawOnceCell::<u64>::new();
+        assert!(cell.try_claim());
+        assert!(!cell.try_claim());
+        assert_eq!(cell.get(), None);
+        unsafe { cell.unclaim() };
+
+        assert!(cell.try_claim());
+        assert_eq!(unsafe { fill(cell.as_ptr()) }, 0);
+        unsafe { cell.assume_init() };
+        assert_eq!(cell.get(), Some(&92));
+        assert!(!cell.try_claim());
+        assert_eq!(
+            cell.as_ptr() as *const u64,
+            cell.get().unwrap() as *const u64
+        );
+    }
+
+    #[test]
+    fn as_slice() {
+        fn total(values: &[u32]) -> u32 {
+            values.iter().sum()
+        }
+
+        let mut cell = unsync::OnceCell::new();
+        assert!(cell.as_slice().is_empty());
+        assert!(cell.as_mut_slice().is_empty());
+        cell.set(40).unwrap();
+        cell.as_mut_slice()[0] += 2;
+        assert_eq!(total(cell.as_slice()), 42);
+
+        let mut cell = sync::OnceCell::new();
+        assert_eq!(cell.as_slice(), &[] as &[String]);
+        cell.set(String::from("a")).unwrap();
+        cell.as_mut_slice().iter_mut().for_each(|s| s.push('b'));
+        assert_eq!(cell.as_slice(), ["ab"]);
+    }
+
+    #[test]
+    fn option_combinators() {
+        let cell = unsync::OnceCell::new();
+        assert_eq!(cell.map_get(String::len), None);
+        assert_eq!(cell.ok_or("empty"), Err("empty"));
+        assert_eq!(cell.get_or(&String::from("default")), "default");
+        assert!(cell.get().is_none());
+        cell.set(String::from("value")).unwrap();
+        assert_eq!(cell.map_get(String::len), Some(5));
+        assert_eq!(
+            cell.ok_or_else(|| unreachable!()).map(String::as_str),
+            Ok::<_, ()>("value")
+        );
+
+        let cell = sync::OnceCell::new();
+        assert_eq!(cell.ok_or_else(|| 404), Err(404));
+        assert_eq!(cell.get_or(&0), &0);
+        cell.set(7).unwrap();
+        assert_eq!(cell.map_get(|n| n * 6), Some(42));
+        assert_eq!(cell.ok_or(404), Ok(&7));
+        assert_eq!(cell.get_or(&0), &7);
+    }
+
+    #[test]
+    fn try_once_cell() {
+        let calls = std::cell::Cell::new(0);
+        let probe = || {
+            calls.set(calls.get() + 1);
+            Err::<u32, _>("no device")
+        };
+        let cell = unsync::TryOnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.get_or_try_init(probe), Err(&"no device"));
+        assert_eq!(cell.get_or_try_init(probe), Err(&"no device"));
+        assert_eq!(calls.get(), 1);
+        assert_eq!(cell.into_inner(), Some(Err("no device")));
+
+        let cell = sync::TryOnceCell::<u32, String>::new();
+        assert_eq!(cell.get_or_try_init(|| Ok(92)), Ok(&92));
+        assert_eq!(cell.get_or_try_init(|| Err(String::new())), Ok(&92));
+        assert_eq!(cell.get(), Some(Ok(&92)));
+    }
+
+    #[test]
+    fn lazy_list() {
+        let computed = std::cell::Cell::new(0);
+        let squares = unsync::LazyList::new((0..).map(|i: u64| {
+            computed.set(computed.get() + 1);
+            i * i
+        }));
+        let first = &squares[0];
+        assert_eq!(squares.get(10), Some(&100));
+        assert_eq!(*first, 0);
+        assert_eq!(computed.get(), 11);
+        assert_eq!(
+            squares.iter().take(4).copied().collect::<Vec<_>>(),
+            [0, 1, 4, 9]
+        );
+        assert_eq!(squares.computed_len(), 11);
+        assert_eq!(computed.get(), 11);
+
+        let short = unsync::LazyList::new(vec!["a", "b", "c"].into_iter());
+        assert_eq!(short.get(5), None);
+        assert_eq!(short.into_iter().copied().collect::<String>(), "abc");
+        assert_eq!(short.get(3), None);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| short[3])).is_err());
+    }
+
+    #[test]
+    fn static_once_cell() {
+        use std::mem::MaybeUninit;
+
+        let buf = Box::leak(Box::new(MaybeUninit::uninit()));
+        let addr = buf.as_ptr();
+        let cell = sync::StaticOnceCell::new(buf);
+        assert_eq!(cell.get(), None);
+        let value: &'static String = cell.get_or_init(|| "frame".to_string());
+        assert_eq!(value as *const String, addr);
+        assert_eq!(cell.set(String::new()), Err(String::new()));
+        assert_eq!(cell.get().map(String::as_str), Some("frame"));
+    }
+
+    #[test]
+    fn policy_backend() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use sync::{Park, PolicyBackend, Retry, Spin};
+
+        let cell: sync::OnceCell<u32, PolicyBackend<Spin, Retry>> =
+            sync::OnceCell::with_backend(PolicyBackend::new());
+        assert!(catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!()))).is_err());
+        assert_eq!(cell.state(), sync::State::Uninit);
+        let timeout = std::time::Duration::MAX;
+        assert_eq!(cell.get_or_init_timeout(|| 92, timeout), Ok(&92));
+
+        let cell: sync::OnceCell<u32, PolicyBackend> = Default::default();
+        assert!(catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!()))).is_err());
+        assert!(cell.is_poisoned());
+
+        let cell = std::sync::Arc::new(sync::OnceCell::with_backend(
+            PolicyBackend::<Park, Retry>::new(),
+        ));
+        let (started, start) = std::sync::mpsc::channel();
+        let slow = {
+            let cell = cell.clone();
+            std::thread::spawn(move || {
+                let _ = catch_unwind(AssertUnwindSafe(|| {
+                    cell.get_or_init(|| {
+                        started.send(()).unwrap();
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        panic!()
+                    })
+                }));
+            })
+        };
+        start.recv().unwrap();
+        // Waits for the failed run, then runs its own.
+        assert_eq!(cell.get_or_init(|| 62), &62);
+        slow.join().unwrap();
+
+        // A lazy can't retry without its closure, and stays poisoned.
+        let lazy: sync::Lazy<u32, _, PolicyBackend<Spin, Retry>> =
+            sync::Lazy::with_backend(|| panic!(), PolicyBackend::new());
+        assert!(catch_unwind(AssertUnwindSafe(|| sync::Lazy::force(&lazy))).is_err());
+        assert!(sync::Lazy::is_poisoned(&lazy));
+        assert_eq!(
+            sync::Lazy::try_deref(&lazy),
+            Err(crate::LazyError::Poisoned)
+        );
+        assert_eq!(sync::Lazy::preinit(&lazy, 92), Err(92));
+        let lazy: sync::Lazy<String, _, PolicyBackend<Spin>> =
+            sync::Lazy::with_backend(|| "spun".to_string(), PolicyBackend::new());
+        assert_eq!(sync::Lazy::force(&lazy.clone()), "spun");
+        assert_eq!(sync::Lazy::get(&lazy), None);
+        assert_eq!(*lazy, "spun");
+    }
+
+    #[test]
+    fn take() {
+        let mut once = unsync::OnceCell::new();
+        assert_eq!(once.take(), None);
+        once.set(String::from("key")).unwrap();
+        assert_eq!(once.take().as_deref(), Some("key"));
+        assert!(once.get().is_none());
+        assert_eq!(once.get_or_init(|| String::from("new key")), "new key");
+
+        let mut once = sync::OnceCell::<u32>::new();
+        let panicked = std::panic::AssertUnwindSafe(|| once.get_or_init(|| panic!()));
+        assert!(std::panic::catch_unwind(panicked).is_err());
+        assert_eq!(once.take(), None);
+        assert_eq!(once.get_or_init(|| 92), &92);
+        assert_eq!(once.take(), Some(92));
+        assert_eq!(once.state(), sync::State::Uninit);
     }
 }